@@ -1,163 +1,469 @@
-use std::error::Error;
+//! Key management for felipeum accounts.
+//!
+//! Secrets, mnemonics and passphrases are read from stdin, one per line, so they never end up in
+//! the shell history or the process list.
+use std::{
+    env,
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+    process, thread,
+    time::Duration,
+};
 
-use ed25519_dalek::Verifier;
-use ed25519_dalek::{ed25519, ExpandedSecretKey, SignatureError};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use felipeum_primitives::{
+    signature::{parse_address, public_key_address, verify_message, Signature, SignatureScheme},
+    transaction::Transaction,
+};
+use felipeum_signature::{
+    hd::DerivationPath,
+    keypair::{
+        keypair_from_mnemonic, keypair_from_mnemonic_path, new_keypair_with_mnemonic, Keypair,
+    },
+    keystore::{Keystore, KeystoreFile},
+    secp256k1::Secp256k1Keypair,
+    signer::transaction_signed,
+};
+use serde_json::Value;
+use vanity::VanityPattern;
+use zeroize::Zeroizing;
 
-use rand::{rngs::OsRng, CryptoRng, RngCore};
+mod vanity;
 
-// #[derive(Clone, Copy, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
-// pub struct Pubkey(pub(crate) [u8; 32]);
-//
-// impl Pubkey {
-//     pub fn new(pubkey_vec: &[u8]) -> Self {
-//         Self(
-//             <[u8; 32]>::try_from(<&[u8]>::clone(&pubkey_vec))
-//                 .expect("Slice must be the same length as a Pubkey"),
-//         )
-//     }
-// }
-// impl fmt::Display for Pubkey {
-//     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-//         write!(f, "{}", bs58::encode(self.0).into_string())
-//     }
-// }
-//
-#[derive(Debug)]
-pub struct Keypair(ed25519_dalek::Keypair);
+#[derive(Parser)]
+#[command(
+    name = "felipeum-keygen",
+    version,
+    about = "Creates and manages felipeum keys"
+)]
+struct Cli {
+    /// How results are printed.
+    #[arg(long, value_enum, default_value_t = Format::Text, global = true)]
+    output: Format,
+    /// Directory of the encrypted keystore, `~/.felipeum/keystore` by default.
+    #[arg(long, global = true)]
+    keystore: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
 
-impl Keypair {
-    pub fn generate<R>(csprng: &mut R) -> Self
-    where
-        R: CryptoRng + RngCore,
-    {
-        Self(ed25519_dalek::Keypair::generate(csprng))
-    }
+#[derive(Subcommand)]
+enum Command {
+    /// Generates a key from a new mnemonic.
+    New {
+        /// Length of the mnemonic, 12 or 24.
+        #[arg(long, default_value_t = 12)]
+        words: usize,
+        #[command(flatten)]
+        mnemonic: MnemonicArgs,
+    },
+    /// Recovers a key from its mnemonic, read from stdin.
+    Recover {
+        /// Derives the key at this SLIP-0010 path, like `m/44'/7337'/0'`, instead of using the
+        /// seed as the key.
+        #[arg(long)]
+        path: Option<DerivationPath>,
+        #[command(flatten)]
+        mnemonic: MnemonicArgs,
+    },
+    /// Prints the public key and address of a key.
+    Pubkey {
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Signs an off-chain message, the signature is never valid for a transaction.
+    Sign {
+        message: String,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Checks a message signature, of either signature scheme.
+    Verify {
+        address: String,
+        message: String,
+        /// Hex encoded.
+        signature: String,
+    },
+    /// Signs the JSON transaction in `file`.
+    SignTx {
+        file: PathBuf,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Manages the encrypted keystore.
+    #[command(subcommand)]
+    Keystore(KeystoreCommand),
+    /// Searches for a key whose address starts or ends with chosen hex digits and stores it.
+    Vanity {
+        #[arg(long, default_value = "")]
+        prefix: String,
+        #[arg(long, default_value = "")]
+        suffix: String,
+        /// All cores by default.
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+}
+
+#[derive(Subcommand)]
+enum KeystoreCommand {
+    /// Adds an encrypted keystore file, or the hex encoded secret key read from stdin if no file
+    /// is given.
+    Import { file: Option<PathBuf> },
+    /// Prints the encrypted keystore file of an account.
+    Export { address: String },
+    /// Lists the addresses of the accounts in the keystore.
+    List,
+}
+
+#[derive(Args)]
+struct MnemonicArgs {
+    /// Reads a BIP39 passphrase protecting the mnemonic.
+    #[arg(long)]
+    bip39_passphrase: bool,
+    /// Adds the key to the keystore instead of printing its secret.
+    #[arg(long)]
+    store: bool,
+}
+
+#[derive(Args)]
+struct KeyArgs {
+    /// Signs with this keystore account, unlocked with a passphrase read from stdin. Without it
+    /// the hex encoded secret key is read from stdin.
+    #[arg(long)]
+    from: Option<String>,
+    /// Scheme of the secret key read from stdin, keystore accounts are always ed25519.
+    #[arg(long, default_value_t = SignatureScheme::Ed25519)]
+    scheme: SignatureScheme,
+}
+
+/// A key of either scheme, see [`load_key`].
+enum Key {
+    Ed25519(Keypair),
+    Secp256k1(Secp256k1Keypair),
+}
 
-    pub fn new() -> Self {
-        let mut rng = OsRng::default();
-        Self::generate(&mut rng)
+impl Key {
+    fn public_key(&self) -> &[u8] {
+        match self {
+            Key::Ed25519(keypair) => keypair.public_key(),
+            Key::Secp256k1(keypair) => keypair.public_key(),
+        }
     }
 
-    pub fn sign_message(&self, message: &[u8]) -> Result<ed25519::Signature, SignatureError> {
-        let expanded: ExpandedSecretKey = (&self.0.secret).into();
+    fn scheme(&self) -> SignatureScheme {
+        match self {
+            Key::Ed25519(_) => SignatureScheme::Ed25519,
+            Key::Secp256k1(_) => SignatureScheme::Secp256k1,
+        }
+    }
 
-        Ok(expanded.sign(&message, &self.0.public).into())
+    fn address(&self) -> Result<String, Box<dyn Error>> {
+        address_of(self.public_key())
     }
 
-    pub fn verify(
-        &self,
-        message: &[u8],
-        signature: &ed25519::Signature,
-    ) -> Result<(), SignatureError> {
-        self.0.public.verify(message, signature)
+    fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(match self {
+            Key::Ed25519(keypair) => keypair.sign_message(message)?.to_bytes().to_vec(),
+            Key::Secp256k1(keypair) => keypair.sign_message(message)?.as_bytes().to_vec(),
+        })
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ed25519_dalek::SignatureError> {
-        ed25519_dalek::Keypair::from_bytes(bytes).map(Self)
+    fn sign_transaction(&self, transaction: &Transaction) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(match self {
+            Key::Ed25519(keypair) => keypair.sign_transaction(transaction)?.to_bytes().to_vec(),
+            Key::Secp256k1(keypair) => keypair.sign_transaction(transaction)?.as_bytes().to_vec(),
+        })
     }
 }
 
-pub fn keypair_from_seed(seed: &[u8]) -> Result<Keypair, Box<dyn Error>> {
-    if seed.len() < ed25519_dalek::SECRET_KEY_LENGTH {
-        return Err("Seed is too short".into());
-    }
-    let secret = ed25519_dalek::SecretKey::from_bytes(&seed[..ed25519_dalek::SECRET_KEY_LENGTH])
-        .map_err(|e| e.to_string())?;
-    let public = ed25519_dalek::PublicKey::from(&secret);
-    let dalek_keypair = ed25519_dalek::Keypair { secret, public };
-    Ok(Keypair(dalek_keypair))
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Text,
+    Json,
 }
 
-pub struct Signature(Vec<u8>);
+/// Named values printed by a command, one per line as text or as a JSON object.
+#[derive(Default)]
+struct Report(Vec<(&'static str, Value)>);
 
-impl Signature {
-    pub fn new(signature_slice: &[u8]) -> Self {
-        Self(signature_slice.to_owned())
+impl Report {
+    fn field(mut self, name: &'static str, value: impl Into<Value>) -> Self {
+        self.0.push((name, value.into()));
+        self
     }
 
-    fn verify_verbose(
-        &self,
-        pubkey_bytes: &[u8],
-        message_bytes: &[u8],
-    ) -> Result<(), SignatureError> {
-        let publickey = ed25519_dalek::PublicKey::from_bytes(pubkey_bytes)?;
-        let signature = self.0.as_slice().try_into()?;
-        publickey.verify_strict(message_bytes, &signature)
+    fn print(self, format: Format) {
+        match format {
+            Format::Json => {
+                let object: serde_json::Map<_, _> = self
+                    .0
+                    .into_iter()
+                    .map(|(name, value)| (name.to_string(), value))
+                    .collect();
+                println!("{}", Value::Object(object));
+            }
+            Format::Text => {
+                for (name, value) in self.0 {
+                    match value {
+                        Value::String(value) => println!("{name}: {value}"),
+                        Value::Array(values) => {
+                            println!("{name}:");
+                            for value in values {
+                                println!(
+                                    "  {}",
+                                    value.as_str().map_or(value.to_string(), str::to_string)
+                                );
+                            }
+                        }
+                        value => println!("{name}: {value}"),
+                    }
+                }
+            }
+        }
     }
+}
 
-    pub fn verify(&self, pubkey_bytes: &[u8], message_bytes: &[u8]) -> bool {
-        self.verify_verbose(pubkey_bytes, message_bytes).is_ok()
+fn main() {
+    let cli = Cli::parse();
+    match run(&cli) {
+        Ok(report) => report.print(cli.output),
+        Err(err) => {
+            eprintln!("error: {err}");
+            process::exit(1);
+        }
     }
 }
 
-fn main() {
-    let keypair: ed25519_dalek::Keypair = ed25519_dalek::Keypair::generate(&mut OsRng);
-    let message = "hello".as_bytes();
-
-    let expanded: ExpandedSecretKey = (&keypair.secret).into();
-    let signature: ed25519_dalek::Signature = expanded.sign(&message, &keypair.public).into();
-
-    println!("Secret key: {:?}", keypair.public.as_bytes());
-    println!("Public key: {:?}", keypair.secret.as_bytes());
-    println!("Message: {:?}", message);
-    println!("Signature: {:?}", signature.to_bytes());
-
-    {
-        let sig = Signature::new(&signature.to_bytes());
-        let is_valid = sig.verify(keypair.public.as_bytes(), message);
-        match is_valid {
-            true => println!("Signature has been proven!"),
-            false => println!("Signature has NOT been proven!"),
+fn run(cli: &Cli) -> Result<Report, Box<dyn Error>> {
+    match &cli.command {
+        Command::New { words, mnemonic } => {
+            let passphrase = bip39_passphrase(mnemonic)?;
+            let (keypair, phrase) = new_keypair_with_mnemonic(*words, &passphrase)?;
+            let report = key_report(cli, &keypair, mnemonic.store)?;
+            Ok(report.field("mnemonic", phrase.as_str()))
+        }
+        Command::Recover { path, mnemonic } => {
+            let phrase = read_secret("mnemonic")?;
+            let passphrase = bip39_passphrase(mnemonic)?;
+            let keypair = match path {
+                Some(path) => keypair_from_mnemonic_path(&phrase, &passphrase, path)?,
+                None => keypair_from_mnemonic(&phrase, &passphrase)?,
+            };
+            key_report(cli, &keypair, mnemonic.store)
+        }
+        Command::Pubkey { key } => {
+            let key = load_key(cli, key)?;
+            Ok(Report::default()
+                .field("public_key", hex::encode(key.public_key()))
+                .field("address", key.address()?))
+        }
+        Command::Sign { message, key } => {
+            let key = load_key(cli, key)?;
+            let signature = key.sign_message(message.as_bytes())?;
+            Ok(Report::default()
+                .field("address", key.address()?)
+                .field("signature", hex::encode(signature)))
+        }
+        Command::Verify {
+            address,
+            message,
+            signature,
+        } => {
+            let address = parse_address(address).ok_or("invalid address")?;
+            let signature = Signature::from_bytes(&hex::decode(signature)?)
+                .ok_or("invalid signature length")?;
+            // exits with an error when invalid, so scripts can rely on the status alone
+            if !verify_message(&address, message.as_bytes(), &signature) {
+                return Err("invalid signature".into());
+            }
+            Ok(Report::default().field("valid", true))
         }
+        Command::SignTx { file, key } => {
+            let transaction: Transaction = serde_json::from_slice(&fs::read(file)?)?;
+            let key = load_key(cli, key)?;
+            let signature = Signature::new(key.scheme(), &key.sign_transaction(&transaction)?);
+            // checked the way the node will, so the sender must be spelled in lowercase
+            if !signature.verify_address(&transaction.from, &transaction.signature_hash()) {
+                return Err(format!(
+                    "transaction is sent from {}, the key is {}",
+                    transaction.from,
+                    key.address()?
+                )
+                .into());
+            }
+            let signed = transaction_signed(&transaction, signature.clone());
+            Ok(Report::default()
+                .field("hash", signed.hash.clone())
+                .field("signature", hex::encode(signature.as_bytes()))
+                .field("transaction", serde_json::to_value(&signed)?))
+        }
+        Command::Keystore(command) => {
+            let keystore = open_keystore(cli)?;
+            match command {
+                KeystoreCommand::Import { file: Some(file) } => {
+                    let file: KeystoreFile = serde_json::from_slice(&fs::read(file)?)?;
+                    Ok(Report::default().field("address", keystore.import(file)?))
+                }
+                KeystoreCommand::Import { file: None } => {
+                    let keypair = read_secret_key()?;
+                    let passphrase = read_secret("passphrase for the keystore")?;
+                    Ok(Report::default().field("address", keystore.insert(&keypair, &passphrase)?))
+                }
+                KeystoreCommand::Export { address } => Ok(Report::default()
+                    .field("keystore", serde_json::to_value(keystore.export(address)?)?)),
+                KeystoreCommand::List => Ok(Report::default().field("addresses", keystore.list()?)),
+            }
+        }
+        Command::Vanity {
+            prefix,
+            suffix,
+            threads,
+        } => vanity(cli, prefix, suffix, *threads),
     }
+}
 
-    //     let mnemonic_type = MnemonicType::for_word_count(12)?;
-    //     let mnemonic = Mnemonic::new(mnemonic_type, Language::English);
-    //     let passphrase: &str = "";
-    //     let seed = Seed::new(&mnemonic, &passphrase);
-    //     let keypair = keypair_from_seed(seed.as_bytes())?;
-    //     println!("pubkey: {}", keypair.pubkey().to_string());
-    //
-    //     let signed = keypair.sign_message("hello".as_bytes()).unwrap();
-    //     println!("signed: {}", signed);
-    //
-    //     let sig = Signature::new(signed.to_string().as_bytes());
-    //     match sig.verify(keypair.pubkey().to_string().as_bytes(), "hello".as_bytes()) {
-    //         Ok(_) => println!("okkkkkkkkkkkkkkkk"),
-    //         Err(_) => println!("Errrrrrrrrrrrrrrrrrrr"),
-    //     };
-    //
-    //     println!(
-    //         "\npubkey: {}\n keypair: {:?}",
-    //         keypair.pubkey(),
-    //         hash_to_hex(keypair.0.to_bytes())
-    //     );
-    //
-    //     Ok(())
+/// Reports the address of `keypair`, and either its secret or where it was stored.
+fn key_report(cli: &Cli, keypair: &Keypair, store: bool) -> Result<Report, Box<dyn Error>> {
+    let report = Report::default().field("address", address_of(keypair.public_key())?);
+    if store {
+        let keystore = open_keystore(cli)?;
+        let passphrase = read_secret("passphrase for the keystore")?;
+        keystore.insert(keypair, &passphrase)?;
+        Ok(report.field("keystore", keystore.dir().display().to_string()))
+    } else {
+        Ok(report.field("secret_key", keypair.export_secret_hex().as_str()))
+    }
 }
+
+fn vanity(
+    cli: &Cli,
+    prefix: &str,
+    suffix: &str,
+    threads: Option<usize>,
+) -> Result<Report, Box<dyn Error>> {
+    let pattern = VanityPattern::new(prefix, suffix)?;
+    let threads = threads.unwrap_or_else(|| thread::available_parallelism().map_or(1, usize::from));
+    // fail before the search rather than after it
+    let keystore = open_keystore(cli)?;
+    let passphrase = read_secret("passphrase for the keystore")?;
+
+    eprintln!(
+        "searching with {threads} threads, about {:.0} keys to generate",
+        pattern.difficulty()
+    );
+    let (keypair, progress) = vanity::search(&pattern, threads, Duration::from_secs(1), |p| {
+        eprint!(
+            "\r{} keys in {}s, {:.0} keys/s, {:.0}% chance so far, expected to take {}s in total    ",
+            p.attempts,
+            p.elapsed.as_secs(),
+            p.rate(),
+            p.probability() * 100.0,
+            p.expected_total().as_secs()
+        );
+    });
+    eprintln!();
+
+    Ok(Report::default()
+        .field("address", keystore.insert(&keypair, &passphrase)?)
+        .field("attempts", progress.attempts)
+        .field("seconds", progress.elapsed.as_secs_f64()))
+}
+
+fn open_keystore(cli: &Cli) -> Result<Keystore, Box<dyn Error>> {
+    let dir = match &cli.keystore {
+        Some(dir) => dir.clone(),
+        None => Path::new(&env::var_os("HOME").ok_or("no home directory, pass --keystore")?)
+            .join(".felipeum")
+            .join("keystore"),
+    };
+    Ok(Keystore::open(dir)?)
+}
+
+fn load_key(cli: &Cli, key: &KeyArgs) -> Result<Key, Box<dyn Error>> {
+    match (&key.from, key.scheme) {
+        (Some(address), SignatureScheme::Ed25519) => {
+            let keystore = open_keystore(cli)?;
+            let passphrase = read_secret(&format!("passphrase of {address}"))?;
+            Ok(Key::Ed25519(keystore.unlock(address, &passphrase)?))
+        }
+        (Some(_), SignatureScheme::Secp256k1) => Err("the keystore only holds ed25519 keys".into()),
+        (None, SignatureScheme::Ed25519) => Ok(Key::Ed25519(read_secret_key()?)),
+        (None, SignatureScheme::Secp256k1) => {
+            let secret = read_secret("hex encoded secret key")?;
+            let secret = Zeroizing::new(hex::decode(secret.trim())?);
+            Ok(Key::Secp256k1(Secp256k1Keypair::from_secret(&secret)?))
+        }
+    }
+}
+
+/// The address of `public_key`, of either scheme.
+fn address_of(public_key: &[u8]) -> Result<String, Box<dyn Error>> {
+    public_key_address(public_key).ok_or_else(|| "invalid public key".into())
+}
+
+fn read_secret_key() -> Result<Keypair, Box<dyn Error>> {
+    let secret = read_secret("hex encoded secret key")?;
+    let secret = Zeroizing::new(hex::decode(secret.trim())?);
+    Ok(Keypair::from_secret(&secret)?)
+}
+
+fn bip39_passphrase(args: &MnemonicArgs) -> Result<Zeroizing<String>, Box<dyn Error>> {
+    if args.bip39_passphrase {
+        read_secret("BIP39 passphrase")
+    } else {
+        Ok(Zeroizing::new(String::new()))
+    }
+}
+
+/// Reads a line from stdin, prompting for it on stderr so the output stays clean.
+fn read_secret(name: &str) -> Result<Zeroizing<String>, Box<dyn Error>> {
+    eprintln!("{name}:");
+    let mut line = Zeroizing::new(String::new());
+    if io::stdin().read_line(&mut line)? == 0 {
+        return Err(format!("expected the {name} on stdin").into());
+    }
+    Ok(Zeroizing::new(
+        line.trim_end_matches(['\r', '\n']).to_string(),
+    ))
+}
+
 #[cfg(test)]
 mod tests {
+    use clap::CommandFactory;
+    use felipeum_primitives::signature::message_hash;
     use felipeum_signature::keypair::new_keypair;
 
     use super::*;
+
     #[test]
     fn test_sign() {
         let message_bytes = r#"{"from":"2","to":"tora","value":10,"nonce":1}"#.as_bytes();
 
         let keypair = new_keypair().unwrap();
-
         let pubkey_bytes = keypair.public_key();
+        let address = hex::encode(pubkey_bytes);
 
-        println!("signing message \n");
         let signature = keypair.sign_message(message_bytes).unwrap();
+        let sig = Signature::new(SignatureScheme::Ed25519, &signature.to_bytes());
 
-        let sig = Signature::new(&signature.to_bytes());
-        println!("signature {:?}", hex::encode(&sig.0));
+        // messages are signed under a prefix, never as they are
+        assert!(!sig.verify(pubkey_bytes, message_bytes));
+        assert!(sig.verify(pubkey_bytes, &message_hash(message_bytes)));
+        assert!(verify_message(&address, message_bytes, &sig));
+    }
 
-        let is_valid = sig.verify(&pubkey_bytes, message_bytes);
-        println!("is_valid {:?}\n", is_valid);
-        assert!(is_valid);
+    #[test]
+    fn secp256k1_keys_sign_for_their_address() {
+        let key = Key::Secp256k1(Secp256k1Keypair::new());
+        let address = key.address().unwrap();
+        assert!(address.starts_with("0x"));
+
+        let signature = Signature::new(key.scheme(), &key.sign_message(b"hello").unwrap());
+        assert!(verify_message(&address, b"hello", &signature));
     }
+
     #[test]
     fn test_signature_fromstr() {
         let message_bytes = "hello".as_bytes();
@@ -171,8 +477,47 @@ mod tests {
             221, 95, 86, 157, 74, 30, 234, 43, 228, 251, 68, 43, 136, 215, 9, 128, 127, 76, 140,
             124, 200, 87, 146, 178, 68, 13,
         ];
-        let sig = Signature::new(signature_bytes);
+        let sig = Signature::new(SignatureScheme::Ed25519, signature_bytes);
         let is_valid = sig.verify(pubkey_bytes, message_bytes);
         assert!(is_valid);
     }
+
+    #[test]
+    fn cli_arguments() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from([
+            "felipeum-keygen",
+            "sign-tx",
+            "tx.json",
+            "--from",
+            "ab",
+            "--output",
+            "json",
+        ])
+        .unwrap();
+        assert!(matches!(cli.output, Format::Json));
+        assert!(matches!(
+            cli.command,
+            Command::SignTx {
+                key: KeyArgs {
+                    from: Some(_),
+                    scheme: SignatureScheme::Ed25519
+                },
+                ..
+            }
+        ));
+        let cli =
+            Cli::try_parse_from(["felipeum-keygen", "pubkey", "--scheme", "secp256k1"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Pubkey {
+                key: KeyArgs {
+                    from: None,
+                    scheme: SignatureScheme::Secp256k1
+                }
+            }
+        ));
+        assert!(Cli::try_parse_from(["felipeum-keygen", "recover", "--path", "m/44'/0"]).is_err());
+    }
 }
@@ -0,0 +1,160 @@
+//! Searches for keypairs whose address starts or ends with chosen hex digits.
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use felipeum_signature::keypair::Keypair;
+use rand::{rngs::OsRng, rngs::StdRng, SeedableRng};
+
+/// Keys each thread generates between updates of the shared attempt counter.
+const BATCH: u64 = 1024;
+
+/// Hex digits the address has to start and end with, matched case insensitively.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VanityPattern {
+    prefix: String,
+    suffix: String,
+}
+
+impl VanityPattern {
+    pub fn new(prefix: &str, suffix: &str) -> Result<Self, String> {
+        let prefix = prefix.to_ascii_lowercase();
+        let suffix = suffix.to_ascii_lowercase();
+        if prefix.is_empty() && suffix.is_empty() {
+            return Err("a prefix or a suffix is needed".to_string());
+        }
+        // addresses are the 64 hex digit public key
+        if prefix.len() + suffix.len() > 64 {
+            return Err("prefix and suffix are longer than an address".to_string());
+        }
+        if let Some(c) = prefix
+            .chars()
+            .chain(suffix.chars())
+            .find(|c| !c.is_ascii_hexdigit())
+        {
+            return Err(format!(
+                "{c:?} is not a hex digit, addresses are hex encoded"
+            ));
+        }
+
+        Ok(Self { prefix, suffix })
+    }
+
+    pub fn matches(&self, address: &str) -> bool {
+        address.starts_with(&self.prefix) && address.ends_with(&self.suffix)
+    }
+
+    /// Keys expected to be generated before one matches.
+    pub fn difficulty(&self) -> f64 {
+        16f64.powi((self.prefix.len() + self.suffix.len()) as i32)
+    }
+}
+
+/// Where a search is at, passed to the progress callback of [`search`].
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub attempts: u64,
+    pub elapsed: Duration,
+    pub difficulty: f64,
+}
+
+impl Progress {
+    /// Keys generated per second.
+    pub fn rate(&self) -> f64 {
+        self.attempts as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Expected duration of the whole search at the current rate, counted from its start rather
+    /// than from now.
+    pub fn expected_total(&self) -> Duration {
+        Duration::from_secs_f64(self.difficulty / self.rate().max(1.0))
+    }
+
+    /// Chance a match would have been found by now.
+    pub fn probability(&self) -> f64 {
+        1.0 - (1.0 - 1.0 / self.difficulty).powf(self.attempts as f64)
+    }
+}
+
+/// Generates keypairs on `threads` threads until one matches `pattern`.
+///
+/// `on_progress` is called from the calling thread every `interval` until then.
+pub fn search(
+    pattern: &VanityPattern,
+    threads: usize,
+    interval: Duration,
+    mut on_progress: impl FnMut(&Progress),
+) -> (Keypair, Progress) {
+    let found = AtomicBool::new(false);
+    let attempts = AtomicU64::new(0);
+    let started = Instant::now();
+    let progress = |attempts: &AtomicU64| Progress {
+        attempts: attempts.load(Ordering::Relaxed),
+        elapsed: started.elapsed(),
+        difficulty: pattern.difficulty(),
+    };
+
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..threads.max(1) {
+            let sender = sender.clone();
+            let (found, attempts) = (&found, &attempts);
+            scope.spawn(move || {
+                // reseeded from the OS once per thread, a syscall per key would dominate
+                let mut rng = StdRng::from_rng(OsRng).expect("the OS has randomness");
+                while !found.load(Ordering::Relaxed) {
+                    for _ in 0..BATCH {
+                        let keypair = Keypair::generate(&mut rng);
+                        if pattern.matches(&hex::encode(keypair.public_key())) {
+                            found.store(true, Ordering::Relaxed);
+                            // only the first match is received, later ones are dropped
+                            let _ = sender.send(keypair);
+                            break;
+                        }
+                    }
+                    attempts.fetch_add(BATCH, Ordering::Relaxed);
+                }
+            });
+        }
+        drop(sender);
+
+        loop {
+            match receiver.recv_timeout(interval) {
+                Ok(keypair) => {
+                    found.store(true, Ordering::Relaxed);
+                    return (keypair, progress(&attempts));
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => on_progress(&progress(&attempts)),
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    unreachable!("threads only stop once a match was sent")
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vanity_search() {
+        assert!(VanityPattern::new("", "").is_err());
+        assert!(VanityPattern::new("xyz", "").is_err());
+        assert!(VanityPattern::new(&"a".repeat(60), "abcde").is_err());
+
+        let pattern = VanityPattern::new("A", "b").unwrap();
+        assert!(pattern.matches("a0b"));
+        assert!(!pattern.matches("b0a"));
+        assert_eq!(pattern.difficulty(), 256.0);
+
+        let (keypair, progress) = search(&pattern, 2, Duration::from_millis(10), |_| {});
+        assert!(pattern.matches(&hex::encode(keypair.public_key())));
+        assert!(progress.probability() >= 0.0);
+    }
+}
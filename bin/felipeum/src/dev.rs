@@ -0,0 +1,163 @@
+//! `felipeum --dev`: a single node sealing a block for every transaction, for local development
+//! and tests that need transactions confirmed right away.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use felipeum_p2p::{block::calculate_hash, builder::build_block, chain::Chain};
+use felipeum_signature::keypair::{keypair_from_mnemonic, Keypair};
+use felipeum_transaction_pool::pool::NewTransactionEvent;
+use log::{error, info};
+use tokio::{
+    sync::mpsc,
+    time::{interval, sleep},
+};
+
+/// Mnemonic of the prefunded dev account, well known so it must never hold real funds.
+pub const DEV_MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+pub fn dev_account() -> Keypair {
+    keypair_from_mnemonic(DEV_MNEMONIC, "").expect("the dev mnemonic is valid")
+}
+
+/// Seals a block every `block_time`, or as soon as a transaction enters the pool without one.
+pub async fn run(
+    mut chain: Chain,
+    mut transactions: mpsc::Receiver<NewTransactionEvent>,
+    block_time: Option<Duration>,
+) {
+    let beneficiary = hex::encode(dev_account().public_key());
+    match block_time {
+        Some(block_time) => {
+            // the pool drops the listener once the receiver is gone
+            drop(transactions);
+            let mut ticker = interval(block_time);
+            loop {
+                ticker.tick().await;
+                seal_block(&mut chain, &beneficiary).await;
+            }
+        }
+        None => {
+            while transactions.recv().await.is_some() {
+                // transactions that arrived together go in the same block
+                while transactions.try_recv().is_ok() {}
+                seal_block(&mut chain, &beneficiary).await;
+            }
+        }
+    }
+}
+
+async fn seal_block(chain: &mut Chain, beneficiary: &str) {
+    // a block has to be dated after the median of the latest ones, and no later than the clock
+    // allows. Blocks sealed in a burst would run ahead of the clock, so wait for it to catch up.
+    let earliest =
+        Duration::from_secs(u64::try_from(chain.median_time_past() + 1).unwrap_or_default());
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    if let Some(wait) = earliest.checked_sub(now) {
+        sleep(wait).await;
+    }
+    let mut block = match build_block(chain, beneficiary, String::new()) {
+        Ok(block) => block,
+        Err(err) => {
+            error!("can't build block: {}", err);
+            return;
+        }
+    };
+    // the dev chain has no difficulty, any nonce seals the block
+    block.hash = hex::encode(calculate_hash(
+        &block,
+        &block.transactions_root(),
+        block.nonce,
+    ));
+    let (id, transactions) = (block.id, block.transactions.len());
+    if chain.try_add_block(block) {
+        info!("sealed block {} with {} transactions", id, transactions);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use felipeum_primitives::{
+        block_tag::BlockTag,
+        chain_spec::ChainSpec,
+        receipt::ReceiptStatus,
+        signature::{Signature, SignatureScheme, TransactionSignature},
+        transaction::{Transaction, TransactionSigned},
+    };
+    use felipeum_transaction_pool::pool::{Pool, PoolTransaction};
+    use tokio::time::timeout;
+
+    use super::*;
+
+    fn dev_chain() -> (Chain, mpsc::Receiver<NewTransactionEvent>) {
+        let pool = Pool::new();
+        let transactions = pool.add_transaction_listener();
+        let spec = ChainSpec::dev(&hex::encode(dev_account().public_key()));
+        (Chain::new(pool, spec), transactions)
+    }
+
+    /// A transfer of the dev account to itself.
+    fn transfer(nonce: u64) -> PoolTransaction {
+        let account = dev_account();
+        let address = hex::encode(account.public_key());
+        let transaction = Transaction::transfer(&address, &address, 1, nonce);
+        let signature = account.sign_transaction(&transaction).unwrap();
+        PoolTransaction::from(TransactionSigned {
+            hash: hex::encode(transaction.signature_hash()),
+            signature: TransactionSignature::Single(Signature::new(
+                SignatureScheme::Ed25519,
+                &signature.to_bytes(),
+            )),
+            transaction,
+        })
+    }
+
+    #[tokio::test]
+    async fn a_transaction_is_sealed_in_its_own_block() {
+        let (chain, transactions) = dev_chain();
+        let (pool, tags, receipts) = (
+            chain.pool.clone(),
+            chain.tags.clone(),
+            chain.receipts.clone(),
+        );
+        tokio::spawn(run(chain, transactions, None));
+
+        let tx = transfer(0);
+        pool.add_transaction(tx.clone()).unwrap();
+        let receipt = timeout(Duration::from_secs(10), async {
+            loop {
+                if let Some(receipt) = receipts.get(&tx.transaction.hash) {
+                    return receipt;
+                }
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the transaction gets sealed");
+        assert_eq!(receipt.status, ReceiptStatus::Success);
+        assert_eq!(receipt.block_number, 1);
+
+        // no other block follows without another transaction
+        sleep(Duration::from_millis(1500)).await;
+        let latest = tags.get(BlockTag::Latest);
+        assert_eq!(latest.number, 1);
+        assert_eq!(latest.hash, receipt.block_hash);
+        assert!(pool.get_all().is_empty());
+    }
+
+    #[tokio::test]
+    async fn blocks_are_sealed_every_block_time() {
+        let (chain, transactions) = dev_chain();
+        let tags = chain.tags.clone();
+        tokio::spawn(run(chain, transactions, Some(Duration::from_secs(1))));
+
+        // blocks keep coming without any transaction
+        timeout(Duration::from_secs(10), async {
+            while tags.get(BlockTag::Latest).number < 2 {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("empty blocks get sealed");
+    }
+}
@@ -1,12 +1,16 @@
+use clap::Parser;
 use felipeum_p2p::{
     chain::Chain,
+    miner::Miner,
     p2p::{
-        get_list_peers, handle_create_block, handle_print_chain, handle_print_peers, AppBehaviour,
-        EventType, LocalChainRequest, CHAIN_TOPIC, KEYS, PEER_ID, POOL_TX_TOPIC,
+        get_list_peers, handle_build_block, handle_create_block, handle_import_future_blocks,
+        handle_mined_block, handle_print_chain, handle_print_mining, handle_print_peers,
+        AppBehaviour, EventType, LocalChainRequest, CHAIN_TOPIC, KEYS, PEER_ID,
     },
 };
-use felipeum_primitives::transaction::TransactionSigned;
+use felipeum_primitives::{chain_spec::ChainSpec, codec::encode, signature};
 use felipeum_rpc::rpc::run_server;
+use felipeum_signature::{keypair, keystore::Keystore};
 use felipeum_transaction_pool::pool::Pool;
 use libp2p::{
     core::upgrade,
@@ -18,77 +22,148 @@ use libp2p::{
     Transport,
 };
 use log::{error, info};
-use parking_lot::Mutex;
-use std::sync::Arc;
+use std::env;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::time::Duration;
 use tokio::{
     io::{stdin, AsyncBufReadExt, BufReader},
     select, spawn,
     sync::mpsc,
-    time::sleep,
+    time::{interval, sleep},
 };
+use zeroize::Zeroizing;
 
-// TODO: kind of replace the MockEthProvider from reth
-pub async fn run_executor(pool: Pool, storage: Storage) {
-    loop {
-        sleep(Duration::from_secs(3)).await;
-        info!("executor");
-        let all = pool.get_all();
-
-        let txs = all
-            .into_iter()
-            .take(2)
-            .map(|tx| (*tx).transaction.clone())
-            .collect();
-        let block = Block { transactions: txs };
-        storage.add_block(block);
-    }
-}
+mod dev;
 
-#[derive(Clone, Debug)]
-pub struct Block {
-    transactions: Vec<TransactionSigned>,
+#[derive(Parser)]
+#[command(about = "Felipeum node")]
+struct Args {
+    /// JSON chain spec, a local development chain when not given
+    #[arg(long)]
+    chain: Option<PathBuf>,
+    /// Address paid the rewards and fees of the blocks this node produces, validators are paid
+    /// themselves without it
+    #[arg(long, value_parser = parse_address)]
+    miner_address: Option<String>,
+    /// Threads mining blocks, one per core when not given
+    #[arg(long)]
+    mining_threads: Option<usize>,
+    /// Validator account sealing blocks under proof of authority, unlocked from the keystore
+    /// with the passphrase in FELIPEUM_SIGNER_PASSPHRASE
+    #[arg(long, value_parser = parse_address)]
+    signer: Option<String>,
+    /// Directory of the encrypted keystore, holding the validator key and the accounts created
+    /// over RPC, `~/.felipeum/keystore` by default
+    #[arg(long)]
+    keystore: Option<PathBuf>,
+    /// Runs a single development node without peers, sealing a block for every transaction
+    #[arg(long, conflicts_with_all = ["chain", "signer", "miner_address"])]
+    dev: bool,
+    /// Seconds between the blocks of the development node, instead of one per transaction
+    #[arg(long, requires = "dev", value_parser = clap::value_parser!(u64).range(1..))]
+    dev_block_time: Option<u64>,
 }
 
-#[derive(Clone)]
-pub struct Storage {
-    // TODO: change vector to hashset
-    blocks: Arc<Mutex<Vec<Block>>>,
+/// Accepts addresses in any case, as long as mixed case ones carry their checksum, and
+/// lowercases them the way accounts are keyed.
+fn parse_address(address: &str) -> Result<String, String> {
+    signature::parse_address(address).ok_or_else(|| format!("{address} is not a valid address"))
 }
 
-impl Storage {
-    fn new() -> Self {
-        Self {
-            blocks: Arc::new(Mutex::new(vec![])),
-        }
-    }
+/// Opens `--keystore`, or `~/.felipeum/keystore` without it.
+fn open_keystore(args: &Args) -> Result<Keystore, Box<dyn Error>> {
+    let dir = match &args.keystore {
+        Some(dir) => dir.clone(),
+        None => Path::new(&env::var_os("HOME").ok_or("no home directory, pass --keystore")?)
+            .join(".felipeum")
+            .join("keystore"),
+    };
+    Ok(Keystore::open(dir)?)
+}
 
-    fn add_block(&self, block: Block) {
-        self.blocks.lock().push(block);
-    }
+/// Unlocks the validator key of `address` from the keystore.
+fn unlock_signer(keystore: &Keystore, address: &str) -> Result<keypair::Keypair, Box<dyn Error>> {
+    let passphrase = Zeroizing::new(
+        env::var("FELIPEUM_SIGNER_PASSPHRASE")
+            .map_err(|_| "FELIPEUM_SIGNER_PASSPHRASE is not set")?,
+    );
+    Ok(keystore.unlock(address, &passphrase)?)
 }
 
 #[tokio::main]
 async fn main() {
     pretty_env_logger::init();
+    let args = Args::parse();
+
+    let spec = match &args.chain {
+        Some(path) => match ChainSpec::load(path) {
+            Ok(spec) => spec,
+            Err(err) => {
+                error!("{}: {}", path.display(), err);
+                process::exit(1);
+            }
+        },
+        None if args.dev => {
+            let account = dev::dev_account();
+            info!("Dev account: {}", hex::encode(account.public_key()));
+            info!("Dev mnemonic: {}", dev::DEV_MNEMONIC);
+            ChainSpec::dev(&hex::encode(account.public_key()))
+        }
+        None => ChainSpec::local(),
+    };
+    let block_time = spec.block_time;
+    let keystore = match open_keystore(&args) {
+        Ok(keystore) => keystore,
+        Err(err) => {
+            error!("can't open the keystore: {}", err);
+            process::exit(1);
+        }
+    };
+    let signer = args
+        .signer
+        .as_deref()
+        .map(|address| match unlock_signer(&keystore, address) {
+            Ok(signer) => signer,
+            Err(err) => {
+                error!("can't unlock signer {}: {}", address, err);
+                process::exit(1);
+            }
+        });
 
     // initialize pool
     let pool = Pool::new();
 
     let mut recv_trans = pool.add_transaction_listener();
 
-    match run_server(pool.clone()).await {
+    let chain = Chain::new(pool.clone(), spec);
+    match run_server(
+        pool.clone(),
+        chain.tags.clone(),
+        chain.receipts.clone(),
+        keystore,
+    )
+    .await
+    {
         Ok(server) => format!("http://{}", server),
         Err(msg) => format!("{}", msg),
     };
 
-    // initialize storage
-    let storage = Storage::new();
+    if args.dev {
+        let block_time = args.dev_block_time.map(Duration::from_secs);
+        dev::run(chain, recv_trans, block_time).await;
+        return;
+    }
 
     // itiliaze p2p
     info!("Peer Id: {}", PEER_ID.clone());
     let (response_sender, mut response_rcv) = mpsc::unbounded_channel();
     let (init_sender, mut init_rcv) = mpsc::unbounded_channel();
+    let (mined_sender, mut mined_rcv) = mpsc::unbounded_channel();
+    let mining_threads = args.mining_threads.unwrap_or_else(|| {
+        std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+    });
 
     let auth_keys = Keypair::<X25519Spec>::new()
         .into_authentic(&KEYS)
@@ -101,11 +176,15 @@ async fn main() {
         .boxed();
 
     let behaviour = AppBehaviour::new(
-        Chain::new(pool.clone()),
+        chain,
+        args.miner_address,
+        Miner::new(mining_threads, mined_sender),
+        signer,
         response_sender,
         init_sender.clone(),
     )
     .await;
+    info!("Genesis: {}", behaviour.app.genesis_hash());
     let mut swarm = SwarmBuilder::new(transp, behaviour, *PEER_ID)
         .executor(Box::new(|fut| {
             spawn(fut);
@@ -122,7 +201,9 @@ async fn main() {
     )
     .expect("swarm can be started");
 
-    spawn(run_executor(pool.clone(), storage.clone()));
+    // blocks are built from the pool on the block time of the chain
+    let mut build_interval = interval(Duration::from_secs(block_time));
+    let mut future_blocks_interval = interval(Duration::from_secs(1));
 
     spawn(async move {
         sleep(Duration::from_secs(1)).await;
@@ -147,6 +228,15 @@ async fn main() {
                 _init = init_rcv.recv() => {
                     Some(EventType::Init)
                 }
+                _ = build_interval.tick() => {
+                    Some(EventType::BuildBlock)
+                }
+                _ = future_blocks_interval.tick() => {
+                    Some(EventType::ImportFutureBlocks)
+                }
+                block = mined_rcv.recv() => {
+                    Some(EventType::Mined(block.expect("the miner lives as long as the swarm")))
+                }
                 _ = swarm.select_next_some() => {
                     None
                 },
@@ -157,7 +247,6 @@ async fn main() {
             match event {
                 EventType::Init => {
                     let peers = get_list_peers(&swarm);
-                    swarm.behaviour_mut().app.genesis();
 
                     info!("connected nodes: {}", peers.len());
                     if !peers.is_empty() {
@@ -167,41 +256,71 @@ async fn main() {
                                 .last()
                                 .expect("at least one peer")
                                 .to_string(),
+                            genesis_hash: swarm.behaviour().app.genesis_hash().to_string(),
                         };
 
-                        let json = serde_json::to_string(&req).expect("can jsonify request");
                         swarm
                             .behaviour_mut()
                             .floodsub
-                            .publish(CHAIN_TOPIC.clone(), json.as_bytes());
+                            .publish(CHAIN_TOPIC.clone(), encode(&req));
                     }
                 }
                 EventType::LocalChainResponse(resp) => {
-                    let json = serde_json::to_string(&resp).expect("can jsonify response");
                     swarm
                         .behaviour_mut()
                         .floodsub
-                        .publish(CHAIN_TOPIC.clone(), json.as_bytes());
+                        .publish(CHAIN_TOPIC.clone(), encode(&resp));
                 }
                 EventType::Input(line) => match line.as_str() {
                     "ls p" => handle_print_peers(&swarm),
+                    "ls m" => handle_print_mining(&swarm),
                     "ls pool" => {
-                        let all = pool.get_all();
-                        println!("{:?}", all);
+                        info!("pending: {:?}", pool.get_all());
+                        info!("queued: {:?}", pool.get_queued());
                     }
                     cmd if cmd.starts_with("ls c") => handle_print_chain(&swarm),
                     cmd if cmd.starts_with("create b") => handle_create_block(cmd, &mut swarm),
                     _ => error!("unknown command"),
                 },
+                EventType::Mined(block) => handle_mined_block(block, &mut swarm),
+                EventType::BuildBlock => handle_build_block(&mut swarm),
+                EventType::ImportFutureBlocks => handle_import_future_blocks(&mut swarm),
                 EventType::NewTx(new_tx) => {
-                    let json = serde_json::to_string(&new_tx).expect("can jsonify response");
+                    let topic = swarm.behaviour().topics.pool_tx.clone();
                     swarm
                         .behaviour_mut()
                         .floodsub
-                        .publish(POOL_TX_TOPIC.clone(), json.as_bytes());
+                        .publish(topic, encode(&new_tx));
                     println!("send p2p: {:?}", new_tx);
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_are_parsed_lowercase() {
+        let ed25519 = hex::encode(keypair::Keypair::new().public_key());
+        assert_eq!(parse_address(&ed25519.to_uppercase()), Ok(ed25519.clone()));
+
+        // from the EIP-55 test vectors
+        let checksummed = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+        let secp256k1 = checksummed.to_lowercase();
+        assert_eq!(parse_address(checksummed), Ok(secp256k1.clone()));
+        assert!(parse_address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD").is_err());
+        assert!(parse_address(&ed25519[2..]).is_err());
+        assert!(parse_address("bob").is_err());
+
+        let args = Args::try_parse_from(["felipeum", "--miner-address", checksummed]).unwrap();
+        assert_eq!(args.miner_address, Some(secp256k1));
+        assert!(Args::try_parse_from(["felipeum", "--signer", "bob"]).is_err());
+        // the dev node pays its blocks to the dev account
+        assert!(
+            Args::try_parse_from(["felipeum", "--dev", "--miner-address", checksummed]).is_err()
+        );
+    }
+}
@@ -1,5 +1,9 @@
 use std::rc::Rc;
 
+use felipeum_primitives::{
+    amount::U256,
+    transaction::{Transaction, TransactionKind, Transfer},
+};
 use felipeum_signature::keypair::{new_keypair, Keypair};
 use leptos::*;
 use leptos_meta::*;
@@ -23,18 +27,29 @@ pub fn App(cx: Scope) -> impl IntoView {
 }
 
 async fn post(to: String, value: String, keypair: Rc<Keypair>) -> String {
+    let value = U256::from_dec_str(&value).unwrap_or_default();
+    let transaction = Transaction {
+        from: hex::encode(keypair.public_key()),
+        nonce: 1,
+        fee: U256::zero(),
+        kind: TransactionKind::Transfer(Transfer {
+            to: to.clone(),
+            value,
+        }),
+        valid_after: None,
+        valid_until: None,
+    };
+    // the node rebuilds the transaction from these fields, the signature covers its RLP encoding
     let tx = json!({
-        "from": hex::encode(keypair.public_key()),
+        "from": transaction.from,
         "to": to,
-        "value": value.parse::<u64>().unwrap(),
-        "nonce": 1,
+        "value": value,
+        "nonce": transaction.nonce,
+        "fee": transaction.fee,
     });
 
     log!("tx: {:?}", tx);
-    let signature = keypair
-        .sign_message(tx.to_string().as_bytes())
-        .unwrap()
-        .to_string();
+    let signature = hex::encode(keypair.sign_transaction(&transaction).unwrap().to_bytes());
 
     let body = json!({
         "jsonrpc": "2.0",
@@ -70,7 +85,7 @@ async fn post(to: String, value: String, keypair: Rc<Keypair>) -> String {
 fn Wallet(cx: Scope) -> impl IntoView {
     let keypair = Rc::new(new_keypair().unwrap());
     let (public_key, set_public_key) = create_signal(cx, hex::encode(keypair.public_key()));
-    let (private_key, set_private_key) = create_signal(cx, hex::encode(keypair.secret()));
+    let (private_key, set_private_key) = create_signal(cx, keypair.export_secret_hex().to_string());
 
     let (to, set_to) = create_signal(cx, String::new());
     let (value, set_value) = create_signal(cx, String::new());
@@ -90,8 +105,8 @@ fn Wallet(cx: Scope) -> impl IntoView {
                     on:click=move |_| {
                         log!("start");
                         let new_keypair = new_keypair().unwrap();
-                        set_private_key(hex::encode(new_keypair.public_key()));
-                        set_public_key(hex::encode(new_keypair.secret()));
+                        set_public_key(hex::encode(new_keypair.public_key()));
+                        set_private_key(new_keypair.export_secret_hex().to_string());
                         log!("end");
                 }>"New Key"</button>
                 </div>
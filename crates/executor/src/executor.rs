@@ -0,0 +1,529 @@
+use std::{collections::BTreeMap, fmt};
+
+use felipeum_primitives::{
+    amount::U256,
+    multisig::{multisig_address, PolicyError},
+    receipt::{Receipt, ReceiptStatus},
+    transaction::{TransactionKind, TransactionSigned, Transfer},
+};
+
+use crate::{
+    state::{Account, State},
+    validator::{validate_signature, ValidationError},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecutionError {
+    Validation(ValidationError),
+    InvalidNonce {
+        expected: u64,
+        got: u64,
+    },
+    InsufficientBalance {
+        balance: U256,
+        required: U256,
+    },
+    InvalidPolicy(PolicyError),
+    AccountExists(String),
+    NotMultisig(String),
+    EmptyBatch,
+    /// The cost of the transaction or a credited balance doesn't fit in a [`U256`].
+    Overflow,
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionError::Validation(err) => write!(f, "{err}"),
+            ExecutionError::InvalidNonce { expected, got } => {
+                write!(f, "invalid nonce {got}, expected {expected}")
+            }
+            ExecutionError::InsufficientBalance { balance, required } => {
+                write!(f, "insufficient balance {balance}, required {required}")
+            }
+            ExecutionError::InvalidPolicy(err) => write!(f, "invalid multisig policy: {err}"),
+            ExecutionError::AccountExists(address) => write!(f, "account {address} exists"),
+            ExecutionError::NotMultisig(address) => {
+                write!(f, "{address} is not a multisig account")
+            }
+            ExecutionError::EmptyBatch => write!(f, "batch has no transfers"),
+            ExecutionError::Overflow => write!(f, "amount overflows"),
+        }
+    }
+}
+
+impl std::error::Error for ExecutionError {}
+
+impl ExecutionError {
+    /// Code of the error in the receipt of a failed transaction. Receipts roots commit to it, so
+    /// a code is never reused or changed, new errors get the next one.
+    pub fn code(&self) -> u16 {
+        match self {
+            ExecutionError::Validation(_) => 1,
+            ExecutionError::InvalidNonce { .. } => 2,
+            ExecutionError::InsufficientBalance { .. } => 3,
+            ExecutionError::InvalidPolicy(_) => 4,
+            ExecutionError::AccountExists(_) => 5,
+            ExecutionError::NotMultisig(_) => 6,
+            ExecutionError::EmptyBatch => 7,
+            ExecutionError::Overflow => 8,
+        }
+    }
+}
+
+impl From<ValidationError> for ExecutionError {
+    fn from(err: ValidationError) -> Self {
+        ExecutionError::Validation(err)
+    }
+}
+
+impl From<PolicyError> for ExecutionError {
+    fn from(err: PolicyError) -> Self {
+        ExecutionError::InvalidPolicy(err)
+    }
+}
+
+/// Validates `tx` against `state` and applies it.
+///
+/// Everything is checked before the first write, so on error `state` is left untouched and a
+/// batch is either applied as a whole or not at all.
+pub fn execute_transaction(
+    state: &mut State,
+    tx: &TransactionSigned,
+) -> Result<(), ExecutionError> {
+    validate_signature(state, tx)?;
+
+    let transaction = &tx.transaction;
+    let sender = &transaction.from;
+    let expected = state.nonce(sender);
+    if transaction.nonce != expected {
+        return Err(ExecutionError::InvalidNonce {
+            expected,
+            got: transaction.nonce,
+        });
+    }
+
+    let cost = transaction.cost().ok_or(ExecutionError::Overflow)?;
+    let balance = state.balance(sender);
+    let remaining = balance
+        .checked_sub(cost)
+        .ok_or(ExecutionError::InsufficientBalance {
+            balance,
+            required: cost,
+        })?;
+
+    let transfers: &[Transfer] = match &transaction.kind {
+        TransactionKind::Transfer(transfer) => std::slice::from_ref(transfer),
+        TransactionKind::Batch(transfers) => {
+            if transfers.is_empty() {
+                return Err(ExecutionError::EmptyBatch);
+            }
+            transfers
+        }
+        TransactionKind::CreateMultisig(policy) => {
+            policy.validate()?;
+            // anyone can send funds to the address before it's created, that doesn't make it
+            // taken
+            let address = multisig_address(sender, transaction.nonce);
+            if state
+                .get(&address)
+                .is_some_and(|account| account.is_multisig() || account.nonce > 0)
+            {
+                return Err(ExecutionError::AccountExists(address));
+            }
+            &[]
+        }
+        TransactionKind::UpdateMultisig(policy) => {
+            policy.validate()?;
+            if !state.get(sender).is_some_and(Account::is_multisig) {
+                return Err(ExecutionError::NotMultisig(sender.clone()));
+            }
+            &[]
+        }
+    };
+
+    // new balances are collected first so a credit overflowing halfway leaves nothing applied
+    let mut balances = BTreeMap::from([(sender.clone(), remaining)]);
+    for transfer in transfers {
+        let current = match balances.get(&transfer.to) {
+            Some(balance) => *balance,
+            None => state.balance(&transfer.to),
+        };
+        let credited = current
+            .checked_add(transfer.value)
+            .ok_or(ExecutionError::Overflow)?;
+        balances.insert(transfer.to.clone(), credited);
+    }
+
+    // every check passed, nothing below can fail
+    for (address, balance) in balances {
+        state.get_mut(&address).balance = balance;
+    }
+    state.get_mut(sender).nonce += 1;
+
+    match &transaction.kind {
+        TransactionKind::CreateMultisig(policy) => {
+            state
+                .get_mut(&multisig_address(sender, transaction.nonce))
+                .multisig = Some(policy.clone());
+        }
+        TransactionKind::UpdateMultisig(policy) => {
+            state.get_mut(sender).multisig = Some(policy.clone());
+        }
+        TransactionKind::Transfer(_) | TransactionKind::Batch(_) => {}
+    }
+
+    Ok(())
+}
+
+/// Applies `tx`, the `index`th transaction of a block, and returns its receipt.
+///
+/// A transaction that's signed, has the next nonce of its sender and whose fee the sender can
+/// afford may be included in a block even if it fails: only its fee is charged and its nonce
+/// used, and the receipt says why it failed. Any other error means it can't be included, and
+/// `state` is left untouched.
+pub fn apply_transaction(
+    state: &mut State,
+    tx: &TransactionSigned,
+    index: u64,
+) -> Result<Receipt, ExecutionError> {
+    let fee = tx.transaction.fee;
+    let error = match execute_transaction(state, tx) {
+        Ok(()) => None,
+        Err(err @ (ExecutionError::Validation(_) | ExecutionError::InvalidNonce { .. })) => {
+            return Err(err)
+        }
+        Err(err) => {
+            let balance = state.balance(&tx.transaction.from);
+            let remaining =
+                balance
+                    .checked_sub(fee)
+                    .ok_or(ExecutionError::InsufficientBalance {
+                        balance,
+                        required: fee,
+                    })?;
+            let sender = state.get_mut(&tx.transaction.from);
+            sender.balance = remaining;
+            sender.nonce += 1;
+            Some(err)
+        }
+    };
+
+    Ok(Receipt {
+        transaction_hash: tx.hash.clone(),
+        block_hash: String::new(),
+        block_number: 0,
+        index,
+        status: match error {
+            None => ReceiptStatus::Success,
+            Some(_) => ReceiptStatus::Failed,
+        },
+        fee_paid: fee,
+        error_code: error.as_ref().map(ExecutionError::code),
+        error: error.as_ref().map(ExecutionError::to_string),
+    })
+}
+
+/// Executes the transactions of a block mined by `beneficiary`, pays it `reward` plus their
+/// fees and returns their receipts.
+///
+/// Stops at the first transaction that can't be included with the ones before it applied, so
+/// blocks that may be invalid are executed on a copy of the state.
+pub fn execute_block(
+    state: &mut State,
+    beneficiary: &str,
+    reward: U256,
+    transactions: &[TransactionSigned],
+) -> Result<Vec<Receipt>, ExecutionError> {
+    let mut payout = reward;
+    let mut receipts = Vec::with_capacity(transactions.len());
+    for (index, tx) in transactions.iter().enumerate() {
+        receipts.push(apply_transaction(state, tx, index as u64)?);
+        payout = payout
+            .checked_add(tx.transaction.fee)
+            .ok_or(ExecutionError::Overflow)?;
+    }
+
+    pay_beneficiary(state, beneficiary, payout)?;
+    Ok(receipts)
+}
+
+/// Credits `amount`, the block reward plus the fees of the block, to `beneficiary`.
+pub fn pay_beneficiary(
+    state: &mut State,
+    beneficiary: &str,
+    amount: U256,
+) -> Result<(), ExecutionError> {
+    let account = state.get_mut(beneficiary);
+    account.balance = account
+        .balance
+        .checked_add(amount)
+        .ok_or(ExecutionError::Overflow)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use felipeum_primitives::{
+        multisig::MultisigPolicy,
+        signature::{Signature, SignatureScheme, SignerSignature, TransactionSignature},
+        transaction::Transaction,
+    };
+    use felipeum_signature::keypair::Keypair;
+
+    use super::*;
+
+    fn address(keypair: &Keypair) -> String {
+        hex::encode(keypair.public_key())
+    }
+
+    fn sign(keypair: &Keypair, transaction: &Transaction) -> SignerSignature {
+        let signature = keypair.sign_transaction(transaction).unwrap();
+        SignerSignature {
+            public_key: address(keypair),
+            signature: Signature::new(SignatureScheme::Ed25519, &signature.to_bytes()),
+        }
+    }
+
+    fn signed(transaction: Transaction, signature: TransactionSignature) -> TransactionSigned {
+        TransactionSigned {
+            hash: hex::encode(transaction.signature_hash()),
+            signature,
+            transaction,
+        }
+    }
+
+    #[test]
+    fn multisig_lifecycle() {
+        let creator = Keypair::new();
+        let signers: Vec<Keypair> = (0..3).map(|_| Keypair::new()).collect();
+        let policy = MultisigPolicy::new(2, signers.iter().map(address).collect());
+        let mut state = State::new();
+
+        let create = Transaction {
+            kind: TransactionKind::CreateMultisig(policy),
+            ..Transaction::transfer(&address(&creator), "", 0, 0)
+        };
+        let signature = TransactionSignature::Single(sign(&creator, &create).signature);
+        execute_transaction(&mut state, &signed(create, signature)).unwrap();
+
+        let multisig = multisig_address(&address(&creator), 0);
+        state.get_mut(&multisig).balance = U256::from(100);
+
+        // a single signature, even of a policy key, can't spend from the account
+        let spend = Transaction::transfer(&multisig, "bob", 40, 0);
+        let single = TransactionSignature::Single(sign(&signers[0], &spend).signature);
+        assert_eq!(
+            execute_transaction(&mut state, &signed(spend.clone(), single)),
+            Err(ExecutionError::Validation(
+                ValidationError::MultisigRequired(multisig.clone())
+            ))
+        );
+
+        let below = TransactionSignature::Multi(vec![sign(&signers[0], &spend)]);
+        assert_eq!(
+            execute_transaction(&mut state, &signed(spend.clone(), below)),
+            Err(ExecutionError::Validation(
+                ValidationError::BelowThreshold {
+                    signers: 1,
+                    threshold: 2
+                }
+            ))
+        );
+
+        let outsider = Keypair::new();
+        let unknown =
+            TransactionSignature::Multi(vec![sign(&signers[0], &spend), sign(&outsider, &spend)]);
+        assert!(execute_transaction(&mut state, &signed(spend.clone(), unknown)).is_err());
+
+        let enough =
+            TransactionSignature::Multi(vec![sign(&signers[0], &spend), sign(&signers[2], &spend)]);
+        execute_transaction(&mut state, &signed(spend, enough)).unwrap();
+        assert_eq!(state.balance(&multisig), U256::from(60));
+        assert_eq!(state.balance("bob"), U256::from(40));
+
+        // rotate to a 1-of-1 policy held by the outsider
+        let update = Transaction {
+            kind: TransactionKind::UpdateMultisig(MultisigPolicy::new(1, vec![address(&outsider)])),
+            ..Transaction::transfer(&multisig, "", 0, 1)
+        };
+        let signatures = TransactionSignature::Multi(vec![
+            sign(&signers[1], &update),
+            sign(&signers[2], &update),
+        ]);
+        execute_transaction(&mut state, &signed(update, signatures)).unwrap();
+
+        let spend = Transaction::transfer(&multisig, "bob", 10, 2);
+        let old =
+            TransactionSignature::Multi(vec![sign(&signers[0], &spend), sign(&signers[1], &spend)]);
+        assert!(execute_transaction(&mut state, &signed(spend.clone(), old)).is_err());
+        let new = TransactionSignature::Multi(vec![sign(&outsider, &spend)]);
+        execute_transaction(&mut state, &signed(spend, new)).unwrap();
+        assert_eq!(state.balance(&multisig), U256::from(50));
+    }
+
+    #[test]
+    fn funding_a_multisig_address_first_doesnt_block_it() {
+        let creator = Keypair::new();
+        let policy = MultisigPolicy::new(1, vec![address(&creator)]);
+        let multisig = multisig_address(&address(&creator), 0);
+        let mut state = State::new();
+        state.get_mut(&multisig).balance = U256::one();
+
+        let create = |state: &mut State, nonce| {
+            let transaction = Transaction {
+                kind: TransactionKind::CreateMultisig(policy.clone()),
+                ..Transaction::transfer(&address(&creator), "", 0, nonce)
+            };
+            let signature = TransactionSignature::Single(sign(&creator, &transaction).signature);
+            execute_transaction(state, &signed(transaction, signature))
+        };
+        create(&mut state, 0).unwrap();
+        assert!(state.get(&multisig).unwrap().is_multisig());
+        assert_eq!(state.balance(&multisig), U256::one());
+
+        // an account already used can't be turned into a multisig
+        let used = multisig_address(&address(&creator), 1);
+        state.get_mut(&used).nonce = 1;
+        assert_eq!(
+            create(&mut state, 1),
+            Err(ExecutionError::AccountExists(used))
+        );
+    }
+
+    #[test]
+    fn batch_is_all_or_nothing() {
+        let payer = Keypair::new();
+        let mut state = State::new();
+        state.get_mut(&address(&payer)).balance = U256::from(100);
+
+        let batch = |nonce, values: &[U256]| Transaction {
+            fee: U256::from(5),
+            kind: TransactionKind::Batch(
+                values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| Transfer {
+                        to: format!("employee-{i}"),
+                        value: *value,
+                    })
+                    .collect(),
+            ),
+            ..Transaction::transfer(&address(&payer), "", 0, nonce)
+        };
+        let execute = |state: &mut State, transaction: Transaction| {
+            let signature = TransactionSignature::Single(sign(&payer, &transaction).signature);
+            execute_transaction(state, &signed(transaction, signature))
+        };
+
+        // 60 + 40 + fee is more than the payer has, nobody gets paid
+        assert_eq!(
+            execute(&mut state, batch(0, &[U256::from(60), U256::from(40)])),
+            Err(ExecutionError::InsufficientBalance {
+                balance: U256::from(100),
+                required: U256::from(105)
+            })
+        );
+        assert!(state.balance("employee-0").is_zero());
+        assert_eq!(state.nonce(&address(&payer)), 0);
+
+        assert_eq!(
+            execute(&mut state, batch(0, &[U256::MAX, U256::one()])),
+            Err(ExecutionError::Overflow)
+        );
+
+        // the second credit overflows after the first one was computed, neither is applied
+        state.get_mut("employee-1").balance = U256::MAX;
+        assert_eq!(
+            execute(&mut state, batch(0, &[U256::from(50), U256::from(30)])),
+            Err(ExecutionError::Overflow)
+        );
+        assert!(state.balance("employee-0").is_zero());
+        assert_eq!(state.balance(&address(&payer)), U256::from(100));
+
+        state.get_mut("employee-1").balance = U256::zero();
+        execute(&mut state, batch(0, &[U256::from(50), U256::from(30)])).unwrap();
+        assert_eq!(state.balance("employee-0"), U256::from(50));
+        assert_eq!(state.balance("employee-1"), U256::from(30));
+        assert_eq!(state.balance(&address(&payer)), U256::from(15));
+        assert_eq!(state.nonce(&address(&payer)), 1);
+    }
+
+    #[test]
+    fn block_pays_reward_and_fees() {
+        let payer = Keypair::new();
+        let mut state = State::new();
+        state.get_mut(&address(&payer)).balance = U256::from(100);
+
+        let transactions: Vec<_> = (0..2)
+            .map(|nonce| {
+                let mut transaction = Transaction::transfer(&address(&payer), "bob", 10, nonce);
+                transaction.fee = U256::from(3);
+                let signature = TransactionSignature::Single(sign(&payer, &transaction).signature);
+                signed(transaction, signature)
+            })
+            .collect();
+        let receipts = execute_block(&mut state, "miner", U256::from(50), &transactions).unwrap();
+        assert_eq!(state.balance("miner"), U256::from(56));
+        assert_eq!(state.balance("bob"), U256::from(20));
+        assert_eq!(state.balance(&address(&payer)), U256::from(74));
+        assert_eq!(receipts.len(), 2);
+        assert_eq!(receipts[1].index, 1);
+        assert_eq!(receipts[1].transaction_hash, transactions[1].hash);
+        assert_eq!(receipts[1].status, ReceiptStatus::Success);
+        assert_eq!(receipts[1].fee_paid, U256::from(3));
+
+        // replaying them fails on the nonce, the miner isn't paid again
+        assert!(execute_block(&mut state, "miner", U256::from(50), &transactions).is_err());
+        assert_eq!(state.balance("miner"), U256::from(56));
+    }
+
+    #[test]
+    fn failed_transaction_pays_its_fee() {
+        let payer = Keypair::new();
+        let mut state = State::new();
+        state.get_mut(&address(&payer)).balance = U256::from(10);
+
+        let sign_all = |transactions: Vec<Transaction>| -> Vec<TransactionSigned> {
+            transactions
+                .into_iter()
+                .map(|mut transaction| {
+                    transaction.fee = U256::from(4);
+                    let signature =
+                        TransactionSignature::Single(sign(&payer, &transaction).signature);
+                    signed(transaction, signature)
+                })
+                .collect()
+        };
+        // the first transfer is more than the balance, the second one succeeds
+        let transactions = sign_all(vec![
+            Transaction::transfer(&address(&payer), "bob", 100, 0),
+            Transaction::transfer(&address(&payer), "bob", 1, 1),
+        ]);
+        let receipts = execute_block(&mut state, "miner", U256::zero(), &transactions).unwrap();
+        assert_eq!(receipts[0].status, ReceiptStatus::Failed);
+        assert_eq!(
+            receipts[0].error.as_deref(),
+            Some("insufficient balance 10, required 104")
+        );
+        assert_eq!(receipts[0].error_code, Some(3));
+        assert_eq!(receipts[0].fee_paid, U256::from(4));
+        assert_eq!(receipts[1].status, ReceiptStatus::Success);
+        assert_eq!(receipts[1].error, None);
+        assert_eq!(state.balance(&address(&payer)), U256::from(1));
+        assert_eq!(state.balance("bob"), U256::from(1));
+        assert_eq!(state.balance("miner"), U256::from(8));
+        assert_eq!(state.nonce(&address(&payer)), 2);
+
+        // a fee the sender can't afford keeps the transaction out of the block
+        let transactions = sign_all(vec![Transaction::transfer(&address(&payer), "bob", 0, 2)]);
+        assert_eq!(
+            apply_transaction(&mut state, &transactions[0], 0),
+            Err(ExecutionError::InsufficientBalance {
+                balance: U256::from(1),
+                required: U256::from(4)
+            })
+        );
+        assert_eq!(state.nonce(&address(&payer)), 2);
+    }
+}
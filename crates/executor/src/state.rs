@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+
+use felipeum_primitives::{
+    amount::U256, chain_spec::ChainSpec, multisig::MultisigPolicy, transaction::keccak256,
+};
+use rlp::{Encodable, RlpStream};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Account {
+    pub nonce: u64,
+    pub balance: U256,
+    /// Set for multisig accounts, which can only be spent from with signatures matching it.
+    pub multisig: Option<MultisigPolicy>,
+}
+
+impl Account {
+    pub fn is_multisig(&self) -> bool {
+        self.multisig.is_some()
+    }
+}
+
+impl Encodable for Account {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(3);
+        s.append(&self.nonce);
+        s.append(&self.balance);
+        s.append(&self.multisig);
+    }
+}
+
+/// Accounts by address.
+///
+/// Kept in a sorted map so iterating over the state is deterministic across nodes.
+#[derive(Clone, Debug, Default)]
+pub struct State {
+    accounts: BTreeMap<String, Account>,
+}
+
+impl State {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The state before the first block of the chain of `spec`.
+    pub fn genesis(spec: &ChainSpec) -> Self {
+        let accounts = spec
+            .genesis
+            .alloc
+            .iter()
+            .map(|(address, balance)| {
+                let account = Account {
+                    balance: *balance,
+                    ..Default::default()
+                };
+                (address.clone(), account)
+            })
+            .collect();
+        Self { accounts }
+    }
+
+    /// Commits to every account, so nodes agreeing on the root agree on the whole state.
+    ///
+    /// Accounts are hashed in address order as `[address, [nonce, balance, multisig]]`. Empty
+    /// accounts count as well, they are created the same way on every node.
+    pub fn root(&self) -> [u8; 32] {
+        let mut s = RlpStream::new_list(self.accounts.len());
+        for (address, account) in &self.accounts {
+            s.begin_list(2);
+            s.append(address);
+            s.append(account);
+        }
+        keccak256(s.out())
+    }
+
+    pub fn get(&self, address: &str) -> Option<&Account> {
+        self.accounts.get(address)
+    }
+
+    /// Returns the account at `address`, creating an empty one if it doesn't exist yet.
+    pub fn get_mut(&mut self, address: &str) -> &mut Account {
+        self.accounts.entry(address.to_string()).or_default()
+    }
+
+    pub fn nonce(&self, address: &str) -> u64 {
+        self.get(address).map_or(0, |account| account.nonce)
+    }
+
+    pub fn balance(&self, address: &str) -> U256 {
+        self.get(address)
+            .map_or_else(U256::zero, |account| account.balance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn genesis_state_root() {
+        let mut spec = ChainSpec::local();
+        let empty = State::genesis(&spec).root();
+        assert_eq!(empty, State::new().root());
+
+        spec.genesis
+            .alloc
+            .insert("alice".to_string(), U256::from(100));
+        spec.genesis.alloc.insert("bob".to_string(), U256::from(5));
+        let state = State::genesis(&spec);
+        assert_eq!(state.balance("alice"), U256::from(100));
+        assert_ne!(state.root(), empty);
+        assert_eq!(state.root(), State::genesis(&spec).root());
+
+        let mut spent = state.clone();
+        spent.get_mut("alice").nonce += 1;
+        assert_ne!(spent.root(), state.root());
+    }
+}
@@ -0,0 +1,103 @@
+use std::{collections::HashSet, fmt};
+
+use felipeum_primitives::{
+    signature::{Signature, TransactionSignature},
+    transaction::TransactionSigned,
+};
+
+use crate::state::State;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The signature of the given address doesn't match the transaction.
+    InvalidSignature(String),
+    /// The sender is a multisig account but the transaction carries a single signature.
+    MultisigRequired(String),
+    /// The transaction carries multiple signatures but the sender isn't a multisig account.
+    NotMultisig(String),
+    /// The key isn't part of the sender's multisig policy.
+    UnknownSigner(String),
+    DuplicateSigner(String),
+    BelowThreshold {
+        signers: usize,
+        threshold: u8,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::InvalidSignature(key) => write!(f, "invalid signature for {key}"),
+            ValidationError::MultisigRequired(address) => {
+                write!(
+                    f,
+                    "{address} is a multisig account and needs multiple signatures"
+                )
+            }
+            ValidationError::NotMultisig(address) => {
+                write!(f, "{address} is not a multisig account")
+            }
+            ValidationError::UnknownSigner(key) => {
+                write!(f, "{key} is not a signer of the account")
+            }
+            ValidationError::DuplicateSigner(key) => write!(f, "{key} signed more than once"),
+            ValidationError::BelowThreshold { signers, threshold } => {
+                write!(
+                    f,
+                    "{signers} signatures are below the threshold of {threshold}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Checks the signatures of `tx` authorize its sender.
+///
+/// Regular accounts need a single signature of the key matching their address, multisig
+/// accounts need signatures of at least `threshold` distinct keys of their policy.
+pub fn validate_signature(state: &State, tx: &TransactionSigned) -> Result<(), ValidationError> {
+    let sender = &tx.transaction.from;
+    let message = tx.transaction.signature_hash();
+    let policy = state
+        .get(sender)
+        .and_then(|account| account.multisig.as_ref());
+
+    match (&tx.signature, policy) {
+        (TransactionSignature::Single(_), Some(_)) => {
+            Err(ValidationError::MultisigRequired(sender.clone()))
+        }
+        (TransactionSignature::Single(signature), None) => verify(sender, signature, &message),
+        (TransactionSignature::Multi(_), None) => Err(ValidationError::NotMultisig(sender.clone())),
+        (TransactionSignature::Multi(signatures), Some(policy)) => {
+            let mut signers = HashSet::new();
+            for signer in signatures {
+                if !policy.contains(&signer.public_key) {
+                    return Err(ValidationError::UnknownSigner(signer.public_key.clone()));
+                }
+                if !signers.insert(&signer.public_key) {
+                    return Err(ValidationError::DuplicateSigner(signer.public_key.clone()));
+                }
+                verify(&signer.public_key, &signer.signature, &message)?;
+            }
+
+            if signers.len() < usize::from(policy.threshold) {
+                return Err(ValidationError::BelowThreshold {
+                    signers: signers.len(),
+                    threshold: policy.threshold,
+                });
+            }
+
+            Ok(())
+        }
+    }
+}
+
+fn verify(address: &str, signature: &Signature, message: &[u8]) -> Result<(), ValidationError> {
+    if signature.verify_address(address, message) {
+        Ok(())
+    } else {
+        Err(ValidationError::InvalidSignature(address.to_string()))
+    }
+}
@@ -1,18 +1,24 @@
 use chrono::prelude::*;
-use log::info;
+use felipeum_primitives::{
+    chain_spec::ChainSpec,
+    codec::{decode_list, expect_items, Codec, MAX_BLOCK_SIZE},
+    receipt::receipts_root,
+    transaction::{keccak256, TransactionSigned},
+};
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
-pub const DIFFICULTY_PREFIX: &str = "00";
-
-pub fn hash_to_binary_representation(hash: &[u8]) -> String {
-    let mut res = String::default();
-
-    for c in hash {
-        res.push_str(&format!("{:b}", c));
+/// Checks `hash` starts with at least `difficulty` zero bits.
+pub fn meets_difficulty(hash: &[u8], difficulty: u32) -> bool {
+    let mut zeros = 0;
+    for byte in hash {
+        zeros += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
     }
-
-    res
+    zeros >= difficulty
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -22,62 +28,136 @@ pub struct Block {
     pub previous_hash: String,
     pub timestamp: i64,
     pub data: String,
+    /// Address paid the block reward and the fees of the block's transactions.
+    pub beneficiary: String,
+    pub transactions: Vec<TransactionSigned>,
+    /// Hex encoded root of the state after the block's transactions.
+    pub state_root: String,
+    /// Hex encoded root of the receipts of the block's transactions.
+    pub receipts_root: String,
     pub nonce: u64,
+    /// Hex encoded signature of the hash by the validator sealing the block, empty under proof
+    /// of work. See [`Consensus`](crate::consensus::Consensus).
+    pub seal: String,
 }
 
 impl Block {
-    pub fn new(id: u64, previous_hash: String, data: String) -> Self {
-        let now = Utc::now();
-        let (nonce, hash) = mine_block(id, now.timestamp(), &previous_hash, &data);
+    /// A block that isn't mined yet, see [`Miner`](crate::miner::Miner).
+    pub fn new(
+        id: u64,
+        previous_hash: String,
+        data: String,
+        beneficiary: String,
+        transactions: Vec<TransactionSigned>,
+        state_root: String,
+    ) -> Self {
         Self {
             id,
-            hash,
+            hash: String::new(),
             previous_hash,
-            timestamp: now.timestamp(),
+            timestamp: Utc::now().timestamp(),
             data,
-            nonce,
+            beneficiary,
+            transactions,
+            state_root,
+            receipts_root: receipts_root(&[]),
+            nonce: 0,
+            seal: String::new(),
         }
     }
+
+    /// The first block of the chain of `spec`, the same on every node.
+    ///
+    /// Its parent hash is the hash of the spec, so chains with different parameters have
+    /// different genesis blocks. It isn't mined, it's only ever compared by hash.
+    pub fn genesis(spec: &ChainSpec, state_root: String) -> Self {
+        let mut block = Self {
+            id: 0,
+            hash: String::new(),
+            previous_hash: hex::encode(spec.hash()),
+            timestamp: i64::try_from(spec.genesis.timestamp)
+                .expect("validated with the chain spec"),
+            data: spec.genesis.extra_data.clone(),
+            beneficiary: String::new(),
+            transactions: vec![],
+            state_root,
+            receipts_root: receipts_root(&[]),
+            nonce: 0,
+            seal: String::new(),
+        };
+        block.hash = hex::encode(calculate_hash(&block, &block.transactions_root(), 0));
+        block
+    }
+
+    pub fn transactions_root(&self) -> String {
+        transactions_root(&self.transactions)
+    }
 }
 
-// this should proabably go into something like validator
-fn mine_block(id: u64, timestamp: i64, previous_hash: &str, data: &str) -> (u64, String) {
-    info!("mining block...");
-    let mut nonce = 0;
+/// Commits to the transactions of a block, so the block hash covers them.
+pub fn transactions_root(transactions: &[TransactionSigned]) -> String {
+    let mut s = RlpStream::new();
+    s.append_list::<TransactionSigned, _>(transactions);
+    hex::encode(keccak256(s.out()))
+}
 
-    loop {
-        if nonce % 100000 == 0 {
-            info!("nonce: {}", nonce);
-        }
-        let hash = calculate_hash(id, timestamp, previous_hash, data, nonce);
-        let binary_hash = hash_to_binary_representation(&hash);
-        if binary_hash.starts_with(DIFFICULTY_PREFIX) {
-            info!(
-                "mined! nonce: {}, hash: {}, binary hash: {}",
-                nonce,
-                hex::encode(&hash),
-                binary_hash
-            );
+impl Encodable for Block {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(11);
+        s.append(&self.id);
+        s.append(&self.hash);
+        s.append(&self.previous_hash);
+        // rlp only knows unsigned integers. A negative timestamp is never valid, it's sent as 0
+        // rather than wrapped around, which is below any genesis timestamp and fails the hash
+        s.append(&u64::try_from(self.timestamp).unwrap_or_default());
+        s.append(&self.data);
+        s.append(&self.beneficiary);
+        s.append_list::<TransactionSigned, _>(&self.transactions);
+        s.append(&self.state_root);
+        s.append(&self.receipts_root);
+        s.append(&self.nonce);
+        s.append(&self.seal);
+    }
+}
 
-            return (nonce, hex::encode(hash));
-        }
-        nonce += 1;
+impl Decodable for Block {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let items = expect_items(rlp, 11)?;
+        let timestamp: u64 = items[3].as_val()?;
+        Ok(Self {
+            id: items[0].as_val()?,
+            hash: items[1].as_val()?,
+            previous_hash: items[2].as_val()?,
+            timestamp: i64::try_from(timestamp)
+                .map_err(|_| DecoderError::Custom("timestamp out of range"))?,
+            data: items[4].as_val()?,
+            beneficiary: items[5].as_val()?,
+            transactions: decode_list(&items[6])?,
+            state_root: items[7].as_val()?,
+            receipts_root: items[8].as_val()?,
+            nonce: items[9].as_val()?,
+            seal: items[10].as_val()?,
+        })
     }
 }
 
+impl Codec for Block {
+    const MAX_SIZE: usize = MAX_BLOCK_SIZE;
+}
+
+/// Hashes the header of `block` with `nonce` in place of its own, the hash commits to the
+/// transactions through `transactions_root`.
 // this should proabably go into something like common maybe
-pub fn calculate_hash(
-    id: u64,
-    timestamp: i64,
-    previous_hash: &str,
-    data: &str,
-    nonce: u64,
-) -> Vec<u8> {
+pub fn calculate_hash(block: &Block, transactions_root: &str, nonce: u64) -> Vec<u8> {
     let data = serde_json::json!({
-        "id": id,
-        "previous_hash": previous_hash,
-        "data": data,
-        "timestamp": timestamp,
+        "id": block.id,
+        "previous_hash": block.previous_hash,
+        "data": block.data,
+        "beneficiary": block.beneficiary,
+        "transactions_root": transactions_root,
+        "state_root": block.state_root,
+        "receipts_root": block.receipts_root,
+        "timestamp": block.timestamp,
         "nonce": nonce
     });
     let mut hasher = Sha256::new();
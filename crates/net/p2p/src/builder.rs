@@ -0,0 +1,67 @@
+//! Assembles the next block out of the transactions waiting in the pool.
+use felipeum_executor::executor::{apply_transaction, pay_beneficiary, ExecutionError};
+use felipeum_primitives::{codec::MAX_BLOCK_SIZE, receipt::receipts_root, transaction::Validity};
+use log::warn;
+
+use crate::{block::Block, chain::Chain};
+
+/// Largest encoded size of the transactions of a block, the rest is left for the header.
+pub const MAX_BLOCK_TRANSACTIONS_SIZE: usize = MAX_BLOCK_SIZE - 4 * 1024;
+
+/// Builds a block on top of the latest block of `chain` paying `beneficiary`, ready to be mined.
+///
+/// Transactions are taken from the pool best first and executed against the latest state. The
+/// ones that fail are included and pay their fee, see [`apply_transaction`]. The ones that can't
+/// be included, aren't in their validity window or don't fit in [`MAX_BLOCK_TRANSACTIONS_SIZE`]
+/// anymore are skipped and stay in the pool.
+pub fn build_block(
+    chain: &Chain,
+    beneficiary: &str,
+    data: String,
+) -> Result<Block, ExecutionError> {
+    let latest_block = chain.blocks.last().expect("there is at least one block");
+    let mut block = Block::new(
+        latest_block.id + 1,
+        latest_block.hash.clone(),
+        data,
+        beneficiary.to_string(),
+        vec![],
+        String::new(),
+    );
+    // blocks produced within the same second need to stay above the median of the latest ones
+    block.timestamp = block.timestamp.max(chain.median_time_past() + 1);
+    let timestamp = u64::try_from(block.timestamp).unwrap_or_default();
+
+    let mut state = chain.state.clone();
+    let mut size = 0;
+    let mut payout = chain.spec.reward.at(block.id);
+    let mut receipts = vec![];
+    for tx in chain.pool.best_transactions() {
+        if size + tx.size > MAX_BLOCK_TRANSACTIONS_SIZE {
+            continue;
+        }
+        let transaction = &tx.transaction;
+        if transaction.transaction.validity(block.id, timestamp) != Validity::Valid {
+            continue;
+        }
+        // the state is left untouched when a transaction can't be included
+        let index = block.transactions.len() as u64;
+        match apply_transaction(&mut state, transaction, index) {
+            Ok(receipt) => receipts.push(receipt),
+            Err(err) => {
+                warn!("skipping transaction {}: {}", transaction.hash, err);
+                continue;
+            }
+        }
+        payout = payout
+            .checked_add(transaction.transaction.fee)
+            .ok_or(ExecutionError::Overflow)?;
+        size += tx.size;
+        block.transactions.push(transaction.clone());
+    }
+    pay_beneficiary(&mut state, beneficiary, payout)?;
+
+    block.state_root = hex::encode(state.root());
+    block.receipts_root = receipts_root(&receipts);
+    Ok(block)
+}
@@ -1,46 +1,245 @@
-use crate::block::{calculate_hash, hash_to_binary_representation, Block, DIFFICULTY_PREFIX};
+use crate::{
+    block::{calculate_hash, Block},
+    consensus::{self, Consensus},
+    finality::{CheckpointVote, Checkpoints},
+    orphans::OrphanPool,
+};
 use chrono::Utc;
 
-use felipeum_transaction_pool::pool::{Pool, PoolError, PoolTransaction};
-use log::{error, warn};
+use felipeum_executor::{executor::execute_block, state::State};
+use felipeum_primitives::{
+    block_tag::{BlockRef, BlockTags},
+    chain_spec::{ChainSpec, Engine, Finality},
+    receipt::{receipts_root, Receipt, ReceiptStore},
+    signature::{verify_transactions, SignatureScheme},
+    transaction::Validity,
+};
+use felipeum_transaction_pool::pool::{OnNewBlockEvent, Pool, PoolError, PoolTransaction};
+use log::{error, info, warn};
+
+/// Number of latest blocks whose median timestamp the next block has to be above.
+pub const MEDIAN_TIME_SPAN: usize = 11;
+
+/// Most blocks held until their timestamp is reached, see [`Chain::import_block`].
+pub const MAX_FUTURE_BLOCKS: usize = 64;
+
+/// What [`Chain::import_block`] did with a block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockImport {
+    Added,
+    /// Ahead of our clock, held until its timestamp is reached.
+    Future,
+    /// Its parent is unknown, held until it arrives. Holds the hash of the block to ask for.
+    Orphan(String),
+    Rejected,
+}
 
 #[derive(Debug)]
 pub struct Chain {
     pub blocks: Vec<Block>,
     pub pool: Pool,
+    pub spec: ChainSpec,
+    pub consensus: Box<dyn Consensus>,
+    /// State after the latest block.
+    pub state: State,
+    /// Blocks from peers that were ahead of our clock.
+    future_blocks: Vec<Block>,
+    /// Blocks from peers whose parent we don't have yet.
+    orphans: OrphanPool,
+    /// Height of the latest final block, the chain is never reorganized below it.
+    finalized: u64,
+    /// Checkpoint votes of the validators under proof of authority.
+    checkpoints: Checkpoints,
+    /// The latest, safe and finalized blocks, shared with the RPC.
+    pub tags: BlockTags,
+    /// Receipts of the transactions of `blocks`, shared with the RPC.
+    pub receipts: ReceiptStore,
+}
+
+/// Median timestamp of the last [`MEDIAN_TIME_SPAN`] of `blocks`. Unlike the latest timestamp, a
+/// few blocks with a wrong clock can't move it.
+fn median_time_past(blocks: &[Block]) -> i64 {
+    let mut timestamps: Vec<i64> = blocks
+        .iter()
+        .rev()
+        .take(MEDIAN_TIME_SPAN)
+        .map(|block| block.timestamp)
+        .collect();
+    timestamps.sort_unstable();
+    timestamps[timestamps.len() / 2]
 }
 
 impl Chain {
-    pub fn new(pool: Pool) -> Self {
-        Self {
-            blocks: vec![],
+    /// A chain holding only the genesis block of `spec`.
+    pub fn new(pool: Pool, spec: ChainSpec) -> Self {
+        let state = State::genesis(&spec);
+        let genesis = Block::genesis(&spec, hex::encode(state.root()));
+        let chain = Self {
+            blocks: vec![genesis],
             pool,
+            consensus: consensus::engine(&spec),
+            spec,
+            state,
+            future_blocks: vec![],
+            orphans: OrphanPool::new(),
+            finalized: 0,
+            checkpoints: Checkpoints::new(),
+            tags: BlockTags::new(),
+            receipts: ReceiptStore::new(),
+        };
+        chain.update_tags();
+        chain
+    }
+
+    /// The next block's timestamp has to be above this.
+    pub fn median_time_past(&self) -> i64 {
+        median_time_past(&self.blocks)
+    }
+
+    /// Latest timestamp a block may have when imported at `now`.
+    fn max_timestamp(&self, now: i64) -> i64 {
+        let drift = i64::try_from(self.spec.max_future_drift).expect("validated with the spec");
+        now.saturating_add(drift)
+    }
+
+    /// Hash of the genesis block, peers on another chain have a different one.
+    pub fn genesis_hash(&self) -> &str {
+        &self.blocks[0].hash
+    }
+
+    /// The latest final block, the genesis block until another one is.
+    pub fn finalized_block(&self) -> &Block {
+        &self.blocks[self.finalized as usize]
+    }
+
+    /// The latest block unlikely to be replaced: half the confirmations deep under proof of
+    /// work, or sealed upon by every validator under proof of authority. Never below the
+    /// finalized block.
+    pub fn safe_block(&self) -> &Block {
+        let depth = match &self.spec.engine {
+            Engine::ProofOfWork => self.spec.finality.confirmations / 2,
+            Engine::ProofOfAuthority { validators } => validators.len() as u64,
+        };
+        let latest_block = self.blocks.last().expect("there is at least one block");
+        let height = latest_block.id.saturating_sub(depth).max(self.finalized);
+        &self.blocks[height as usize]
+    }
+
+    /// Counts the checkpoint vote of a validator, returns false if it's invalid or was counted
+    /// before. Votes may arrive before their block, up to a checkpoint interval ahead.
+    pub fn add_checkpoint_vote(&mut self, vote: CheckpointVote) -> bool {
+        let Engine::ProofOfAuthority { validators } = &self.spec.engine else {
+            return false;
+        };
+        let interval = self.spec.finality.checkpoint_interval;
+        let latest_block = self.blocks.last().expect("there is at least one block");
+        if !validators.contains(&vote.validator)
+            || !vote.height.is_multiple_of(interval)
+            || vote.height <= self.finalized
+            || vote.height > latest_block.id + interval
+        {
+            return false;
+        }
+        if !vote.verify() {
+            warn!("invalid checkpoint vote from {}", vote.validator);
+            return false;
+        }
+        if !self.checkpoints.add(vote) {
+            return false;
+        }
+        self.update_finality();
+        true
+    }
+
+    /// Moves the finalized block up to the deepest confirmed block under proof of work, or the
+    /// latest checkpoint signed by a quorum of validators under proof of authority.
+    pub fn update_finality(&mut self) {
+        let latest = self.blocks.last().expect("there is at least one block").id;
+        let finalized = match &self.spec.engine {
+            Engine::ProofOfWork => latest.saturating_sub(self.spec.finality.confirmations),
+            Engine::ProofOfAuthority { validators } => {
+                let quorum = Finality::quorum(validators.len());
+                let interval = self.spec.finality.checkpoint_interval;
+                (self.finalized + 1..=latest)
+                    .rev()
+                    .filter(|height| height.is_multiple_of(interval))
+                    .find(|height| {
+                        let block = &self.blocks[*height as usize];
+                        self.checkpoints.votes(block.id, &block.hash) >= quorum
+                    })
+                    .unwrap_or(self.finalized)
+            }
+        };
+        if finalized > self.finalized {
+            info!("finalized block {}", finalized);
+            self.finalized = finalized;
+            self.checkpoints.prune(finalized);
         }
+        self.update_tags();
     }
 
-    pub fn genesis(&mut self) {
-        let genesis_block = Block {
-            id: 0,
-            hash: "0000f816a87f806bb0073dcf026a64fb40c946b5abee2573702828694d5b4c43".to_string(),
-            previous_hash: String::from("genesis"),
-            timestamp: Utc::now().timestamp(),
-            data: String::from("genesis"),
-            nonce: 2836,
+    fn update_tags(&self) {
+        let block_ref = |block: &Block| BlockRef {
+            number: block.id,
+            hash: block.hash.clone(),
+            timestamp: block.timestamp,
         };
+        self.tags.set(
+            block_ref(self.blocks.last().expect("there is at least one block")),
+            block_ref(self.safe_block()),
+            block_ref(self.finalized_block()),
+        );
+    }
+
+    /// Executes the transactions of `block` on top of `state` and pays its beneficiary, returning
+    /// the new state and the receipts of the transactions. `None` if one of them can't be
+    /// included or the resulting state or receipts don't match the roots the block claims.
+    pub fn apply_block(&self, state: &State, block: &Block) -> Option<(State, Vec<Receipt>)> {
+        let mut state = state.clone();
+        let reward = self.spec.reward.at(block.id);
+        let mut receipts =
+            match execute_block(&mut state, &block.beneficiary, reward, &block.transactions) {
+                Ok(receipts) => receipts,
+                Err(err) => {
+                    warn!(
+                        "block with id: {} has invalid transactions: {}",
+                        block.id, err
+                    );
+                    return None;
+                }
+            };
+        if hex::encode(state.root()) != block.state_root {
+            warn!("block with id: {} has wrong state root", block.id);
+            return None;
+        }
+        if receipts_root(&receipts) != block.receipts_root {
+            warn!("block with id: {} has wrong receipts root", block.id);
+            return None;
+        }
+
+        for receipt in &mut receipts {
+            receipt.block_hash = block.hash.clone();
+            receipt.block_number = block.id;
+        }
+        Some((state, receipts))
+    }
 
-        self.blocks.push(genesis_block);
+    fn is_block_valid(&self, block: &Block, ancestors: &[Block]) -> bool {
+        self.is_header_valid(block, ancestors) && self.has_valid_signatures(&[block])
     }
 
-    fn is_block_valid(&self, block: &Block, previous_block: &Block) -> bool {
+    /// Everything but the transaction signatures, which are checked in batches, and how far
+    /// ahead of our clock the block is.
+    fn is_header_valid(&self, block: &Block, ancestors: &[Block]) -> bool {
+        let previous_block = ancestors.last().expect("there is at least one block");
         if block.previous_hash != previous_block.hash {
             warn!("block with id: {} has wrong previous hash", block.id);
             return false;
-        } else if !hash_to_binary_representation(
-            &hex::decode(&block.hash).expect("can decode from hex"),
-        )
-        .starts_with(DIFFICULTY_PREFIX)
-        {
-            warn!("block with id: {} has invalid difficulty", block.id);
+        } else if let Err(err) = self.consensus.verify_seal(block) {
+            warn!("block with id: {} has invalid seal: {}", block.id, err);
+            return false;
+        } else if !SignatureScheme::is_valid_address(&block.beneficiary) {
+            warn!("block with id: {} has invalid beneficiary", block.id);
             return false;
         } else if block.id != previous_block.id + 1 {
             warn!(
@@ -48,51 +247,142 @@ impl Chain {
                 block.id, previous_block.id
             );
             return false;
+        } else if block.timestamp <= median_time_past(ancestors) {
+            warn!(
+                "block with id: {} has a timestamp before the median of the latest blocks",
+                block.id
+            );
+            return false;
         } else if hex::encode(calculate_hash(
-            block.id,
-            block.timestamp,
-            &block.previous_hash,
-            &block.data,
+            block,
+            &block.transactions_root(),
             block.nonce,
         )) != block.hash
         {
             warn!("block with id: {} has invalid hash", block.id);
             return false;
+        } else if !self.has_transactions_in_window(block) {
+            warn!(
+                "block with id: {} has transactions outside their validity window",
+                block.id
+            );
+            return false;
         }
 
         true
     }
 
-    fn is_chain_valid(&self, chain: &[Block]) -> bool {
+    fn has_transactions_in_window(&self, block: &Block) -> bool {
+        let timestamp = u64::try_from(block.timestamp).unwrap_or_default();
+        block
+            .transactions
+            .iter()
+            .all(|tx| tx.transaction.validity(block.id, timestamp) == Validity::Valid)
+    }
+
+    /// Verifies the signatures of all transactions of `blocks` in a single batch.
+    fn has_valid_signatures(&self, blocks: &[&Block]) -> bool {
+        let transactions: Vec<_> = blocks
+            .iter()
+            .flat_map(|block| block.transactions.iter().map(move |tx| (block.id, tx)))
+            .collect();
+        match verify_transactions(transactions.iter().map(|(_, tx)| *tx)) {
+            Ok(()) => true,
+            Err(invalid) => {
+                for (id, tx) in invalid.into_iter().map(|index| transactions[index]) {
+                    warn!(
+                        "block with id: {} has transaction {} with an invalid signature",
+                        id, tx.hash
+                    );
+                }
+                false
+            }
+        }
+    }
+
+    /// Checks `chain` starts at our genesis block and replays it, returning the state after its
+    /// last block and the receipts of all of its transactions.
+    fn validate_chain(&self, chain: &[Block]) -> Option<(State, Vec<Receipt>)> {
+        match chain.first() {
+            Some(genesis) if genesis.hash == self.genesis_hash() => {}
+            _ => {
+                warn!("chain doesn't start at our genesis block");
+                return None;
+            }
+        }
+        let max_timestamp = self.max_timestamp(Utc::now().timestamp());
         for i in 0..chain.len() {
             if i == 0 {
                 continue;
             }
-            let first = chain.get(i - 1).expect("has to exist");
-            let second = chain.get(i).expect("has to exist");
-            if !self.is_block_valid(second, first) {
-                return false;
+            let block = chain.get(i).expect("has to exist");
+            if block.timestamp > max_timestamp {
+                warn!("block with id: {} is too far in the future", block.id);
+                return None;
+            }
+            if !self.is_header_valid(block, &chain[..i]) {
+                return None;
             }
         }
-        true
+        // syncing is where most signatures get checked, so they go in one batch for all blocks
+        let blocks: Vec<&Block> = chain.iter().skip(1).collect();
+        if !self.has_valid_signatures(&blocks) {
+            return None;
+        }
+
+        blocks.into_iter().try_fold(
+            (State::genesis(&self.spec), vec![]),
+            |(state, mut receipts), block| {
+                let (state, block_receipts) = self.apply_block(&state, block)?;
+                receipts.extend(block_receipts);
+                Some((state, receipts))
+            },
+        )
     }
 
+    /// Picks the longest valid chain of the two. `remote` is never picked if it doesn't contain
+    /// our finalized block, however long it is. Call [`Chain::update_finality`] once the chosen
+    /// chain is in place.
     pub fn choose_chain(&mut self, local: Vec<Block>, remote: Vec<Block>) -> Vec<Block> {
-        let is_local_valid = self.is_chain_valid(&local);
-        let is_remote_valid = self.is_chain_valid(&remote);
+        let local_state = self.validate_chain(&local);
+        let finalized = self.finalized_block();
+        let remote_state = match remote.get(finalized.id as usize) {
+            Some(block) if block.hash == finalized.hash => self.validate_chain(&remote),
+            _ => {
+                warn!(
+                    "remote chain reverts the finalized block {}, ignoring it",
+                    finalized.id
+                );
+                None
+            }
+        };
 
-        if is_local_valid && is_remote_valid {
-            if local.len() >= remote.len() {
+        match (local_state, remote_state) {
+            (Some((state, receipts)), Some(_)) if local.len() >= remote.len() => {
+                self.state = state;
+                self.receipts.reset(receipts);
                 local
-            } else {
+            }
+            (_, Some((state, receipts))) => {
+                self.state = state;
+                self.receipts.reset(receipts);
+                let fork = local
+                    .iter()
+                    .zip(&remote)
+                    .position(|(ours, theirs)| ours.hash != theirs.hash)
+                    .unwrap_or_else(|| local.len().min(remote.len()));
+                self.reorganize_pool(&local[fork..], &remote[fork..]);
                 remote
             }
-        } else if is_remote_valid && !is_local_valid {
-            remote
-        } else if !is_remote_valid && is_remote_valid {
-            local
-        } else {
-            panic!("local and remote chains are both invalid");
+            (Some((state, receipts)), None) => {
+                self.state = state;
+                self.receipts.reset(receipts);
+                local
+            }
+            (None, None) => {
+                error!("local and remote chains are both invalid, keeping local");
+                local
+            }
         }
     }
 
@@ -108,12 +398,528 @@ impl Chain {
         }
     }
 
-    pub fn try_add_block(&mut self, block: Block) {
+    /// Adds `block` on top of the latest block if it's valid, returns whether it was added.
+    ///
+    /// Meant for blocks produced by this node, whose timestamp may be slightly ahead to stay
+    /// above the median, see [`Chain::import_block`] for blocks from peers.
+    pub fn try_add_block(&mut self, block: Block) -> bool {
+        let hash = block.hash.clone();
+        if !self.add_block(block) {
+            return false;
+        }
+        self.connect_orphans(hash);
+        true
+    }
+
+    fn add_block(&mut self, block: Block) -> bool {
+        if block.timestamp > self.max_timestamp(Utc::now().timestamp()) {
+            error!("could not add block - too far in the future");
+            return false;
+        }
+        if !self.is_block_valid(&block, &self.blocks) {
+            error!("could not add block - invalid");
+            return false;
+        }
+        match self.apply_block(&self.state, &block) {
+            Some((state, receipts)) => {
+                self.state = state;
+                self.receipts.insert(receipts);
+                self.notify_pool(&block);
+                self.blocks.push(block);
+                self.update_finality();
+                true
+            }
+            None => {
+                error!("could not add block - invalid state transition");
+                false
+            }
+        }
+    }
+
+    /// Adds `block` from a peer on top of the latest block. If it's ahead of our clock, but by
+    /// no more than the max future drift of the spec, it's held until
+    /// [`Chain::import_future_blocks`] finds its timestamp reached.
+    ///
+    /// A block above the latest one whose parent we don't have is held in the orphan pool, and
+    /// added along with its held descendants once the parent arrives.
+    pub fn import_block(&mut self, block: Block) -> BlockImport {
+        let now = Utc::now().timestamp();
         let latest_block = self.blocks.last().expect("there is at least one block");
-        if self.is_block_valid(&block, latest_block) {
-            self.blocks.push(block);
-        } else {
+        let is_orphan = block.id > latest_block.id
+            && block.previous_hash != latest_block.hash
+            && !self
+                .blocks
+                .iter()
+                .any(|known| known.hash == block.previous_hash);
+        if is_orphan {
+            return self.hold_orphan(block, now);
+        }
+
+        let hash = block.hash.clone();
+        let import = self.import_connected(block, now);
+        if import == BlockImport::Added {
+            self.connect_orphans(hash);
+        }
+        import
+    }
+
+    /// Keeps `block` until its parent arrives. Its ancestors are unknown, so only what the block
+    /// proves on its own is checked: its seal, hash and timestamp.
+    fn hold_orphan(&mut self, block: Block, now: i64) -> BlockImport {
+        if block.timestamp > self.max_timestamp(now) {
+            error!("could not add block - too far in the future");
+            return BlockImport::Rejected;
+        }
+        if let Err(err) = self.consensus.verify_seal(&block) {
+            warn!(
+                "orphan block with id: {} has invalid seal: {}",
+                block.id, err
+            );
+            return BlockImport::Rejected;
+        }
+        if hex::encode(calculate_hash(
+            &block,
+            &block.transactions_root(),
+            block.nonce,
+        )) != block.hash
+        {
+            warn!("orphan block with id: {} has invalid hash", block.id);
+            return BlockImport::Rejected;
+        }
+        info!(
+            "block with id: {} has an unknown parent, holding it",
+            block.id
+        );
+        let hash = block.hash.clone();
+        self.orphans.insert(block);
+        BlockImport::Orphan(self.orphans.missing_ancestor(&hash))
+    }
+
+    /// Adds the orphans descending from the block `hash`, parents before their children.
+    fn connect_orphans(&mut self, hash: String) {
+        let now = Utc::now().timestamp();
+        let mut parents = vec![hash];
+        while let Some(parent) = parents.pop() {
+            for orphan in self.orphans.take_children(&parent) {
+                let hash = orphan.hash.clone();
+                if self.import_connected(orphan, now) == BlockImport::Added {
+                    info!("connected orphan block {}", hash);
+                    parents.push(hash);
+                }
+            }
+        }
+    }
+
+    fn import_connected(&mut self, block: Block, now: i64) -> BlockImport {
+        if block.timestamp <= now {
+            return match self.add_block(block) {
+                true => BlockImport::Added,
+                false => BlockImport::Rejected,
+            };
+        }
+
+        if block.timestamp > self.max_timestamp(now) {
+            error!("could not add block - too far in the future");
+            return BlockImport::Rejected;
+        }
+        if !self.is_block_valid(&block, &self.blocks) {
             error!("could not add block - invalid");
+            return BlockImport::Rejected;
+        }
+        if self
+            .future_blocks
+            .iter()
+            .any(|queued| queued.hash == block.hash)
+        {
+            return BlockImport::Future;
+        }
+        if self.future_blocks.len() >= MAX_FUTURE_BLOCKS {
+            warn!(
+                "too many future blocks, dropping block with id: {}",
+                block.id
+            );
+            return BlockImport::Rejected;
+        }
+        info!(
+            "block with id: {} is {}s ahead, holding it",
+            block.id,
+            block.timestamp - now
+        );
+        self.future_blocks.push(block);
+        BlockImport::Future
+    }
+
+    /// Adds the held blocks whose timestamp was reached, returns how many were added.
+    pub fn import_future_blocks(&mut self) -> usize {
+        let now = Utc::now().timestamp();
+        let (mut due, later): (Vec<_>, Vec<_>) = std::mem::take(&mut self.future_blocks)
+            .into_iter()
+            .partition(|block| block.timestamp <= now);
+        self.future_blocks = later;
+
+        due.sort_by_key(|block| block.id);
+        let mut added = 0;
+        for block in due {
+            // another block may have taken its place while it was held
+            if self.try_add_block(block) {
+                added += 1;
+            }
+        }
+        added
+    }
+
+    /// Lets the pool drop the transactions mined in `block` and re-check the queued ones.
+    /// Brings the pool in line with a chain adopted in place of ours: drops what the `adopted`
+    /// blocks mined and gives the transactions of the `dropped` blocks another chance, unless
+    /// the new chain used their nonce already. Expects `self.state` to be the adopted chain's.
+    fn reorganize_pool(&self, dropped: &[Block], adopted: &[Block]) {
+        for block in adopted {
+            self.notify_pool(block);
+        }
+        for tx in dropped.iter().flat_map(|block| &block.transactions) {
+            let nonce = self
+                .state
+                .get(&tx.transaction.from)
+                .map_or(0, |account| account.nonce);
+            if tx.transaction.nonce < nonce {
+                continue;
+            }
+            if let Err(err) = self.pool.add_transaction(PoolTransaction::from(tx.clone())) {
+                warn!("can't re-queue transaction {}: {:?}", tx.hash, err);
+            }
+        }
+    }
+
+    pub fn notify_pool(&self, block: &Block) {
+        self.pool.on_new_block(OnNewBlockEvent {
+            hash: block.hash.clone(),
+            number: block.id,
+            timestamp: u64::try_from(block.timestamp).unwrap_or_default(),
+            mined_transactions: block
+                .transactions
+                .iter()
+                .cloned()
+                .map(PoolTransaction::from)
+                .collect(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use felipeum_signature::keypair::Keypair;
+
+    use felipeum_primitives::{
+        amount::U256,
+        block_tag::BlockTag,
+        signature::{Signature, TransactionSignature},
+        transaction::{Transaction, TransactionSigned},
+    };
+
+    use super::*;
+    use crate::{builder::build_block, consensus::ProofOfAuthority};
+
+    fn chain() -> Chain {
+        let mut spec = ChainSpec::local();
+        spec.difficulty = 0;
+        Chain::new(Pool::new(), spec)
+    }
+
+    /// The next block of `chain` dated `timestamp`, sealed for a chain without difficulty.
+    fn next_block(chain: &Chain, data: &str, timestamp: i64) -> Block {
+        let beneficiary = hex::encode(Keypair::new().public_key());
+        let mut block = build_block(chain, &beneficiary, data.to_string()).unwrap();
+        block.timestamp = timestamp;
+        block.hash = hex::encode(calculate_hash(
+            &block,
+            &block.transactions_root(),
+            block.nonce,
+        ));
+        block
+    }
+
+    /// A proof of authority chain of the validators `keys`, with a checkpoint every 2 blocks.
+    fn poa_chain(keys: &[Keypair]) -> (Chain, ProofOfAuthority) {
+        let validators: Vec<String> = keys
+            .iter()
+            .map(|key| hex::encode(key.public_key()))
+            .collect();
+        let mut spec = ChainSpec::local();
+        spec.engine = Engine::ProofOfAuthority {
+            validators: validators.clone(),
+        };
+        spec.finality.checkpoint_interval = 2;
+        (
+            Chain::new(Pool::new(), spec),
+            ProofOfAuthority { validators },
+        )
+    }
+
+    /// The next block of `chain`, sealed by whichever of `keys` is in turn.
+    fn sealed_block(
+        chain: &Chain,
+        engine: &ProofOfAuthority,
+        keys: &[Keypair],
+        data: &str,
+    ) -> Block {
+        let latest_block = chain.blocks.last().unwrap();
+        let mut block = build_block(chain, &engine.validators[0], data.to_string()).unwrap();
+        block.timestamp = latest_block.timestamp + 1;
+        let in_turn = engine.in_turn(block.id);
+        let key = keys
+            .iter()
+            .find(|key| hex::encode(key.public_key()) == in_turn)
+            .unwrap();
+        engine.seal(&mut block, key).unwrap();
+        block
+    }
+
+    #[test]
+    fn timestamps_stay_above_the_median() {
+        let mut chain = chain();
+        let genesis = chain.blocks[0].timestamp;
+        let block = next_block(&chain, "x", genesis);
+        assert_eq!(chain.import_block(block), BlockImport::Rejected);
+
+        for i in 1..=MEDIAN_TIME_SPAN as i64 {
+            let block = next_block(&chain, "x", genesis + i);
+            assert_eq!(chain.import_block(block), BlockImport::Added);
+        }
+        assert_eq!(chain.median_time_past(), genesis + 6);
+        let block = next_block(&chain, "x", genesis + 6);
+        assert_eq!(chain.import_block(block), BlockImport::Rejected);
+        // earlier than the latest block, but above the median
+        let block = next_block(&chain, "x", genesis + 7);
+        assert_eq!(chain.import_block(block), BlockImport::Added);
+    }
+
+    #[test]
+    fn orphans_connect_once_their_parent_arrives() {
+        let mut source = chain();
+        let genesis = source.blocks[0].timestamp;
+        for i in 1..=4 {
+            let block = next_block(&source, "x", genesis + i);
+            assert!(source.try_add_block(block));
+        }
+        let blocks = &source.blocks;
+
+        let mut chain = chain();
+        let hash = |id: usize| blocks[id].hash.clone();
+        assert_eq!(
+            chain.import_block(blocks[4].clone()),
+            BlockImport::Orphan(hash(3))
+        );
+        assert_eq!(
+            chain.import_block(blocks[2].clone()),
+            BlockImport::Orphan(hash(1))
+        );
+        assert_eq!(
+            chain.import_block(blocks[3].clone()),
+            BlockImport::Orphan(hash(1))
+        );
+        assert_eq!(chain.blocks.len(), 1);
+
+        assert_eq!(chain.import_block(blocks[1].clone()), BlockImport::Added);
+        assert_eq!(chain.blocks.len(), 5);
+        assert_eq!(chain.blocks.last().unwrap().hash, hash(4));
+        assert_eq!(chain.state.root(), source.state.root());
+        assert!(chain.orphans.is_empty());
+    }
+
+    #[test]
+    fn blocks_beyond_the_drift_are_rejected() {
+        let mut chain = chain();
+        let drift = chain.spec.max_future_drift as i64;
+        let block = next_block(&chain, "x", Utc::now().timestamp() + drift + 5);
+        assert!(!chain.try_add_block(block.clone()));
+        assert_eq!(chain.import_block(block), BlockImport::Rejected);
+        assert_eq!(chain.blocks.len(), 1);
+    }
+
+    #[test]
+    fn future_blocks_are_imported_once_due() {
+        let mut chain = chain();
+        let timestamp = Utc::now().timestamp() + 2;
+        let block = next_block(&chain, "x", timestamp);
+        assert_eq!(chain.import_block(block.clone()), BlockImport::Future);
+        assert_eq!(chain.import_block(block), BlockImport::Future);
+        assert_eq!(chain.import_future_blocks(), 0);
+        assert_eq!(chain.blocks.len(), 1);
+
+        while Utc::now().timestamp() < timestamp {
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(chain.import_future_blocks(), 1);
+        assert_eq!(chain.blocks.len(), 2);
+        assert!(chain.future_blocks.is_empty());
+    }
+
+    #[test]
+    fn future_blocks_are_bounded() {
+        let mut chain = chain();
+        let timestamp = Utc::now().timestamp() + 5;
+        for i in 0..MAX_FUTURE_BLOCKS {
+            let block = next_block(&chain, &i.to_string(), timestamp);
+            assert_eq!(chain.import_block(block), BlockImport::Future);
+        }
+        let block = next_block(&chain, "one too many", timestamp);
+        assert_eq!(chain.import_block(block), BlockImport::Rejected);
+        // the ones held already are still known
+        let held = chain.future_blocks[0].clone();
+        assert_eq!(chain.import_block(held), BlockImport::Future);
+        assert_eq!(chain.future_blocks.len(), MAX_FUTURE_BLOCKS);
+    }
+
+    #[test]
+    fn checkpoints_finalize_with_a_quorum() {
+        let keys: Vec<Keypair> = (0..4).map(|_| Keypair::new()).collect();
+        let (mut chain, engine) = poa_chain(&keys);
+        for _ in 0..4 {
+            let block = sealed_block(&chain, &engine, &keys, "x");
+            assert!(chain.try_add_block(block));
+        }
+        let checkpoint = chain.blocks[2].clone();
+        let vote = |key: &Keypair| CheckpointVote::sign(&checkpoint, key);
+
+        assert!(
+            !chain.add_checkpoint_vote(CheckpointVote::sign(&chain.blocks[3].clone(), &keys[0]))
+        );
+        assert!(!chain.add_checkpoint_vote(vote(&Keypair::new())));
+        let mut forged = vote(&keys[0]);
+        forged.hash = chain.blocks[1].hash.clone();
+        assert!(!chain.add_checkpoint_vote(forged));
+
+        // three of four validators are a quorum
+        assert!(chain.add_checkpoint_vote(vote(&keys[0])));
+        assert!(!chain.add_checkpoint_vote(vote(&keys[0])));
+        assert!(chain.add_checkpoint_vote(vote(&keys[1])));
+        assert_eq!(chain.finalized_block().id, 0);
+        assert!(chain.add_checkpoint_vote(vote(&keys[2])));
+        assert_eq!(chain.finalized_block().hash, checkpoint.hash);
+        assert_eq!(chain.tags.get(BlockTag::Finalized).number, 2);
+        // final checkpoints take no more votes
+        assert!(!chain.add_checkpoint_vote(vote(&keys[3])));
+    }
+
+    #[test]
+    fn checkpoint_votes_may_arrive_an_interval_ahead() {
+        let keys = [Keypair::new()];
+        let (mut chain, engine) = poa_chain(&keys);
+        let (mut ahead, _) = poa_chain(&keys);
+        for _ in 0..4 {
+            let block = sealed_block(&ahead, &engine, &keys, "x");
+            assert!(ahead.try_add_block(block));
+        }
+        assert!(chain.try_add_block(ahead.blocks[1].clone()));
+
+        let vote = |height: usize| CheckpointVote::sign(&ahead.blocks[height], &keys[0]);
+        assert!(!chain.add_checkpoint_vote(vote(4)));
+        assert!(chain.add_checkpoint_vote(vote(2)));
+        assert_eq!(chain.finalized_block().id, 0);
+        // counted once the block arrives
+        assert!(chain.try_add_block(ahead.blocks[2].clone()));
+        assert_eq!(chain.finalized_block().id, 2);
+    }
+
+    #[test]
+    fn adopted_chains_reorganize_the_pool() {
+        let keys: Vec<Keypair> = (0..2).map(|_| Keypair::new()).collect();
+        let mut spec = ChainSpec::local();
+        spec.difficulty = 0;
+        for key in &keys {
+            let address = hex::encode(key.public_key());
+            spec.genesis.alloc.insert(address, U256::from(100));
+        }
+        let transfer = |key: &Keypair, nonce| {
+            let from = hex::encode(key.public_key());
+            let transaction = Transaction::transfer(&from, &from, 1, nonce);
+            let signature = key.sign_transaction(&transaction).unwrap();
+            PoolTransaction::from(TransactionSigned {
+                hash: hex::encode(transaction.signature_hash()),
+                signature: TransactionSignature::Single(Signature::new(
+                    SignatureScheme::Ed25519,
+                    &signature.to_bytes(),
+                )),
+                transaction,
+            })
+        };
+        let (first, second, other) = (
+            transfer(&keys[0], 0),
+            transfer(&keys[0], 1),
+            transfer(&keys[1], 0),
+        );
+        let timestamp = Utc::now().timestamp();
+
+        // our block mines both transactions of the first key
+        let mut chain = Chain::new(Pool::new(), spec.clone());
+        chain.pool.add_transaction(first.clone()).unwrap();
+        chain.pool.add_transaction(second.clone()).unwrap();
+        let block = next_block(&chain, "x", timestamp);
+        assert_eq!(block.transactions.len(), 2);
+        assert!(chain.try_add_block(block));
+        chain.pool.add_transaction(other.clone()).unwrap();
+
+        // a longer fork mines only the first one along with the other key's
+        let mut remote = Chain::new(Pool::new(), spec);
+        remote.pool.add_transaction(first.clone()).unwrap();
+        remote.pool.add_transaction(other.clone()).unwrap();
+        for offset in 0..2 {
+            let block = next_block(&remote, "fork", timestamp + offset);
+            assert!(remote.try_add_block(block));
+        }
+        assert_eq!(remote.blocks[1].transactions.len(), 2);
+
+        let local = chain.blocks.clone();
+        let chosen = chain.choose_chain(local, remote.blocks.clone());
+        assert_eq!(chosen.last().unwrap().hash, remote.blocks[2].hash);
+        let pending: Vec<_> = chain
+            .pool
+            .get_all()
+            .iter()
+            .map(|tx| tx.transaction.hash.clone())
+            .collect();
+        assert_eq!(pending, vec![second.transaction.hash.clone()]);
+    }
+
+    #[test]
+    fn remote_chains_keep_the_finalized_block() {
+        let keys: Vec<Keypair> = (0..3).map(|_| Keypair::new()).collect();
+        let (mut chain, engine) = poa_chain(&keys);
+        let block = sealed_block(&chain, &engine, &keys, "x");
+        assert!(chain.try_add_block(block));
+        let fork = sealed_block(&chain, &engine, &keys, "fork");
+        for _ in 0..2 {
+            let block = sealed_block(&chain, &engine, &keys, "x");
+            assert!(chain.try_add_block(block));
+        }
+        let checkpoint = chain.blocks[2].clone();
+        for key in &keys {
+            assert!(chain.add_checkpoint_vote(CheckpointVote::sign(&checkpoint, key)));
+        }
+        assert_eq!(chain.finalized_block().id, 2);
+
+        // a longer chain forking below the finalized block
+        let (mut remote, _) = poa_chain(&keys);
+        assert!(remote.try_add_block(chain.blocks[1].clone()));
+        assert!(remote.try_add_block(fork));
+        for _ in 0..4 {
+            let block = sealed_block(&remote, &engine, &keys, "x");
+            assert!(remote.try_add_block(block));
+        }
+        let local = chain.blocks.clone();
+        let chosen = chain.choose_chain(local.clone(), remote.blocks.clone());
+        assert_eq!(chosen.last().unwrap().hash, local.last().unwrap().hash);
+
+        // a longer chain that keeps it is fine
+        let (mut remote, _) = poa_chain(&keys);
+        for block in &local[1..] {
+            assert!(remote.try_add_block(block.clone()));
         }
+        let block = sealed_block(&remote, &engine, &keys, "x");
+        assert!(remote.try_add_block(block));
+        let chosen = chain.choose_chain(local, remote.blocks.clone());
+        assert_eq!(chosen.len(), remote.blocks.len());
     }
 }
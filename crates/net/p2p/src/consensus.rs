@@ -0,0 +1,226 @@
+//! Rules on who may produce a block and how it's sealed, picked by the chain spec.
+use std::fmt;
+
+use felipeum_primitives::{
+    chain_spec::{ChainSpec, Engine},
+    signature::{verify_message, Signature, SignatureScheme},
+};
+use felipeum_signature::keypair::Keypair;
+
+use crate::block::{calculate_hash, meets_difficulty, Block};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsensusError {
+    /// The block hash has fewer leading zero bits than the difficulty.
+    InsufficientWork,
+    /// A proof of work block carries a seal, which its hash doesn't cover.
+    UnexpectedSeal,
+    MissingSeal,
+    /// The seal isn't a signature of the block hash by the in-turn validator.
+    InvalidSeal {
+        expected: String,
+    },
+    /// Only the in-turn validator may seal a block.
+    NotInTurn {
+        expected: String,
+    },
+}
+
+impl fmt::Display for ConsensusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConsensusError::InsufficientWork => write!(f, "insufficient proof of work"),
+            ConsensusError::UnexpectedSeal => write!(f, "proof of work blocks have no seal"),
+            ConsensusError::MissingSeal => write!(f, "missing seal"),
+            ConsensusError::InvalidSeal { expected } => {
+                write!(f, "seal isn't signed by the in-turn validator {expected}")
+            }
+            ConsensusError::NotInTurn { expected } => {
+                write!(f, "not in turn, {expected} seals this block")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConsensusError {}
+
+/// Checks blocks are sealed the way the engine of the chain requires. Everything else about a
+/// block, from its parent to its transactions, is the same for all engines and checked by the
+/// [`Chain`](crate::chain::Chain).
+pub trait Consensus: fmt::Debug + Send {
+    fn verify_seal(&self, block: &Block) -> Result<(), ConsensusError>;
+}
+
+/// The engine selected by `spec`.
+pub fn engine(spec: &ChainSpec) -> Box<dyn Consensus> {
+    match &spec.engine {
+        Engine::ProofOfWork => Box::new(ProofOfWork {
+            difficulty: spec.difficulty,
+        }),
+        Engine::ProofOfAuthority { validators } => Box::new(ProofOfAuthority {
+            validators: validators.clone(),
+        }),
+    }
+}
+
+/// Blocks are mined, see [`Miner`](crate::miner::Miner).
+#[derive(Debug, Clone)]
+pub struct ProofOfWork {
+    pub difficulty: u32,
+}
+
+impl Consensus for ProofOfWork {
+    fn verify_seal(&self, block: &Block) -> Result<(), ConsensusError> {
+        if !block.seal.is_empty() {
+            return Err(ConsensusError::UnexpectedSeal);
+        }
+        if !hex::decode(&block.hash).is_ok_and(|hash| meets_difficulty(&hash, self.difficulty)) {
+            return Err(ConsensusError::InsufficientWork);
+        }
+        Ok(())
+    }
+}
+
+/// Blocks are signed by the validators taking turns, a block that isn't signed by the
+/// validator in turn for its height is invalid.
+#[derive(Debug, Clone)]
+pub struct ProofOfAuthority {
+    pub validators: Vec<String>,
+}
+
+impl ProofOfAuthority {
+    /// The validator sealing the block at `height`.
+    pub fn in_turn(&self, height: u64) -> &str {
+        &self.validators[(height % self.validators.len() as u64) as usize]
+    }
+
+    /// Hashes `block` and signs the hash with `signer`, which has to be in turn.
+    pub fn seal(&self, block: &mut Block, signer: &Keypair) -> Result<(), ConsensusError> {
+        let expected = self.in_turn(block.id);
+        if hex::encode(signer.public_key()) != expected {
+            return Err(ConsensusError::NotInTurn {
+                expected: expected.to_string(),
+            });
+        }
+        block.nonce = 0;
+        let hash = calculate_hash(block, &block.transactions_root(), block.nonce);
+        let signature = signer
+            .sign_message(&hash)
+            .expect("signing with an in-memory key can't fail");
+        block.hash = hex::encode(hash);
+        block.seal = hex::encode(signature.to_bytes());
+        Ok(())
+    }
+}
+
+impl Consensus for ProofOfAuthority {
+    fn verify_seal(&self, block: &Block) -> Result<(), ConsensusError> {
+        if block.seal.is_empty() {
+            return Err(ConsensusError::MissingSeal);
+        }
+        let expected = self.in_turn(block.id);
+        let signed = match (hex::decode(&block.hash), hex::decode(&block.seal)) {
+            (Ok(hash), Ok(seal)) => verify_message(
+                expected,
+                &hash,
+                &Signature::new(SignatureScheme::Ed25519, &seal),
+            ),
+            _ => false,
+        };
+        if !signed {
+            return Err(ConsensusError::InvalidSeal {
+                expected: expected.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(id: u64) -> Block {
+        Block::new(
+            id,
+            "parent".to_string(),
+            "data".to_string(),
+            "beneficiary".to_string(),
+            vec![],
+            "state".to_string(),
+        )
+    }
+
+    fn validators(keys: &[Keypair]) -> ProofOfAuthority {
+        ProofOfAuthority {
+            validators: keys
+                .iter()
+                .map(|key| hex::encode(key.public_key()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn sealed_blocks_verify() {
+        let keys = [Keypair::new(), Keypair::new()];
+        let engine = validators(&keys);
+        let mut block = block(3);
+        engine.seal(&mut block, &keys[1]).unwrap();
+        assert_eq!(engine.verify_seal(&block), Ok(()));
+        assert_eq!(
+            block.hash,
+            hex::encode(calculate_hash(&block, &block.transactions_root(), 0))
+        );
+
+        // the seal covers the hash, which covers the block
+        let mut tampered = block.clone();
+        tampered.data = "other".to_string();
+        tampered.hash = hex::encode(calculate_hash(&tampered, &tampered.transactions_root(), 0));
+        assert!(matches!(
+            engine.verify_seal(&tampered),
+            Err(ConsensusError::InvalidSeal { .. })
+        ));
+    }
+
+    #[test]
+    fn only_the_in_turn_validator_seals() {
+        let keys = [Keypair::new(), Keypair::new()];
+        let engine = validators(&keys);
+        let mut block = block(3);
+        assert_eq!(
+            engine.seal(&mut block, &keys[0]),
+            Err(ConsensusError::NotInTurn {
+                expected: hex::encode(keys[1].public_key()),
+            })
+        );
+
+        // a validator sealing out of turn anyway
+        validators(&keys[..1]).seal(&mut block, &keys[0]).unwrap();
+        assert_eq!(
+            engine.verify_seal(&block),
+            Err(ConsensusError::InvalidSeal {
+                expected: hex::encode(keys[1].public_key()),
+            })
+        );
+    }
+
+    #[test]
+    fn seals_match_the_engine() {
+        let key = Keypair::new();
+        let mut sealed = block(1);
+        validators(std::slice::from_ref(&key))
+            .seal(&mut sealed, &key)
+            .unwrap();
+        let pow = ProofOfWork { difficulty: 0 };
+        assert_eq!(
+            pow.verify_seal(&sealed),
+            Err(ConsensusError::UnexpectedSeal)
+        );
+        sealed.seal.clear();
+        assert_eq!(pow.verify_seal(&sealed), Ok(()));
+        assert_eq!(
+            validators(&[key]).verify_seal(&sealed),
+            Err(ConsensusError::MissingSeal)
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Checkpoints signed by the validators under proof of authority, a block is final once more than
+//! two thirds of them signed it. See [`Finality`](felipeum_primitives::chain_spec::Finality).
+use std::collections::{BTreeMap, BTreeSet};
+
+use felipeum_primitives::{
+    codec::{expect_items, Codec},
+    signature::{verify_message, Signature, SignatureScheme},
+};
+use felipeum_signature::keypair::Keypair;
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use serde::{Deserialize, Serialize};
+
+use crate::block::Block;
+
+/// A validator's signature of the block `hash` at checkpoint `height`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckpointVote {
+    pub height: u64,
+    pub hash: String,
+    pub validator: String,
+    /// Hex encoded signature of [`checkpoint_message`] by the validator.
+    pub signature: String,
+}
+
+/// What validators sign for a checkpoint. It's tagged so a vote is never mistaken for the seal of
+/// the same block.
+pub fn checkpoint_message(height: u64, hash: &str) -> Vec<u8> {
+    let mut s = RlpStream::new_list(3);
+    s.append(&"felipeum checkpoint");
+    s.append(&height);
+    s.append(&hash);
+    s.out().to_vec()
+}
+
+impl CheckpointVote {
+    pub fn sign(block: &Block, signer: &Keypair) -> Self {
+        let signature = signer
+            .sign_message(&checkpoint_message(block.id, &block.hash))
+            .expect("signing with an in-memory key can't fail");
+        Self {
+            height: block.id,
+            hash: block.hash.clone(),
+            validator: hex::encode(signer.public_key()),
+            signature: hex::encode(signature.to_bytes()),
+        }
+    }
+
+    /// Checks the vote is signed by its validator, not that the validator is one of the chain.
+    pub fn verify(&self) -> bool {
+        hex::decode(&self.signature).is_ok_and(|signature| {
+            verify_message(
+                &self.validator,
+                &checkpoint_message(self.height, &self.hash),
+                &Signature::new(SignatureScheme::Ed25519, &signature),
+            )
+        })
+    }
+}
+
+impl Encodable for CheckpointVote {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(4);
+        s.append(&self.height);
+        s.append(&self.hash);
+        s.append(&self.validator);
+        s.append(&self.signature);
+    }
+}
+
+impl Decodable for CheckpointVote {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let items = expect_items(rlp, 4)?;
+        Ok(Self {
+            height: items[0].as_val()?,
+            hash: items[1].as_val()?,
+            validator: items[2].as_val()?,
+            signature: items[3].as_val()?,
+        })
+    }
+}
+
+impl Codec for CheckpointVote {
+    const MAX_SIZE: usize = 1024;
+}
+
+/// Validators that signed each checkpoint, until it's final.
+#[derive(Debug, Default)]
+pub struct Checkpoints {
+    votes: BTreeMap<(u64, String), BTreeSet<String>>,
+}
+
+impl Checkpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts `vote`, which has to be verified already. Returns false if it was counted before.
+    pub fn add(&mut self, vote: CheckpointVote) -> bool {
+        self.votes
+            .entry((vote.height, vote.hash))
+            .or_default()
+            .insert(vote.validator)
+    }
+
+    /// Validators that signed the block `hash` at `height`.
+    pub fn votes(&self, height: u64, hash: &str) -> usize {
+        self.votes
+            .get(&(height, hash.to_string()))
+            .map_or(0, BTreeSet::len)
+    }
+
+    /// Drops the votes for checkpoints at or below the finalized block, they no longer matter.
+    pub fn prune(&mut self, finalized: u64) {
+        self.votes = self.votes.split_off(&(finalized + 1, String::new()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint(height: u64, hash: &str) -> Block {
+        let mut block = Block::new(
+            height,
+            String::new(),
+            String::new(),
+            String::new(),
+            vec![],
+            String::new(),
+        );
+        block.hash = hash.to_string();
+        block
+    }
+
+    #[test]
+    fn votes_are_signed_by_their_validator() {
+        let vote = CheckpointVote::sign(&checkpoint(16, "aa"), &Keypair::new());
+        assert!(vote.verify());
+        assert_eq!(
+            rlp::decode::<CheckpointVote>(&rlp::encode(&vote)),
+            Ok(vote.clone())
+        );
+
+        let mut moved = vote.clone();
+        moved.hash = "bb".to_string();
+        assert!(!moved.verify());
+        let mut stolen = vote;
+        stolen.validator = hex::encode(Keypair::new().public_key());
+        assert!(!stolen.verify());
+    }
+
+    #[test]
+    fn each_validator_counts_once_per_block() {
+        let keys = [Keypair::new(), Keypair::new()];
+        let mut checkpoints = Checkpoints::new();
+        assert!(checkpoints.add(CheckpointVote::sign(&checkpoint(16, "aa"), &keys[0])));
+        assert!(!checkpoints.add(CheckpointVote::sign(&checkpoint(16, "aa"), &keys[0])));
+        assert!(checkpoints.add(CheckpointVote::sign(&checkpoint(16, "aa"), &keys[1])));
+        // a competing block at the same height has its own votes
+        assert!(checkpoints.add(CheckpointVote::sign(&checkpoint(16, "bb"), &keys[0])));
+        assert_eq!(checkpoints.votes(16, "aa"), 2);
+        assert_eq!(checkpoints.votes(16, "bb"), 1);
+        assert_eq!(checkpoints.votes(32, "aa"), 0);
+    }
+
+    #[test]
+    fn prune_drops_final_checkpoints() {
+        let key = Keypair::new();
+        let mut checkpoints = Checkpoints::new();
+        for height in [16, 32, 48] {
+            checkpoints.add(CheckpointVote::sign(&checkpoint(height, "aa"), &key));
+        }
+        checkpoints.prune(32);
+        assert_eq!(checkpoints.votes(16, "aa"), 0);
+        assert_eq!(checkpoints.votes(32, "aa"), 0);
+        assert_eq!(checkpoints.votes(48, "aa"), 1);
+    }
+}
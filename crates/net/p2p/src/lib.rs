@@ -1,4 +1,9 @@
 // todo: remove when not needed
 pub mod block;
+pub mod builder;
 pub mod chain;
+pub mod consensus;
+pub mod finality;
+pub mod miner;
+pub mod orphans;
 pub mod p2p;
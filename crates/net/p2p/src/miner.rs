@@ -0,0 +1,248 @@
+//! Proof of work on dedicated threads, so mining never blocks the swarm's event loop.
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::info;
+use tokio::sync::mpsc;
+
+use crate::block::{calculate_hash, meets_difficulty, Block};
+
+/// Nonces each thread tries between checks for an abort and updates of the hash counter.
+const BATCH: u64 = 1024;
+
+/// Hashes tried for a block, read while it's being mined.
+#[derive(Debug, Clone, Copy)]
+pub struct MiningStats {
+    pub height: u64,
+    pub hashes: u64,
+    pub elapsed: Duration,
+}
+
+impl MiningStats {
+    /// Hashes per second.
+    pub fn hashrate(&self) -> f64 {
+        self.hashes as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+#[derive(Debug)]
+struct Job {
+    height: u64,
+    /// Set once a thread found the nonce or the job was aborted, threads stop when they see it.
+    done: Arc<AtomicBool>,
+    hashes: Arc<AtomicU64>,
+    started: Instant,
+}
+
+impl Job {
+    fn stats(&self) -> MiningStats {
+        MiningStats {
+            height: self.height,
+            hashes: self.hashes.load(Ordering::Relaxed),
+            elapsed: self.started.elapsed(),
+        }
+    }
+}
+
+/// Mines one block at a time on `threads` threads, each trying its own share of the nonces.
+///
+/// Mined blocks are sent to the channel given to [`Miner::new`], for the event loop to import
+/// and broadcast them.
+#[derive(Debug)]
+pub struct Miner {
+    threads: u64,
+    sender: mpsc::UnboundedSender<Block>,
+    job: Option<Job>,
+}
+
+impl Miner {
+    pub fn new(threads: usize, sender: mpsc::UnboundedSender<Block>) -> Self {
+        Self {
+            threads: threads.max(1) as u64,
+            sender,
+            job: None,
+        }
+    }
+
+    /// Starts looking for a nonce sealing `block`, aborting the block mined until now.
+    pub fn start(&mut self, block: Block, difficulty: u32) {
+        if let Some(job) = &self.job {
+            job.done.store(true, Ordering::Relaxed);
+        }
+        let job = Job {
+            height: block.id,
+            done: Arc::new(AtomicBool::new(false)),
+            hashes: Arc::new(AtomicU64::new(0)),
+            started: Instant::now(),
+        };
+        info!(
+            "mining block {} on {} threads, difficulty {}",
+            block.id, self.threads, difficulty
+        );
+
+        let block = Arc::new(block);
+        // the root doesn't change with the nonce, no need to hash all transactions every time
+        let transactions_root = Arc::new(block.transactions_root());
+        for first in 0..self.threads {
+            let step = self.threads;
+            let (block, transactions_root) = (block.clone(), transactions_root.clone());
+            let (done, hashes) = (job.done.clone(), job.hashes.clone());
+            let sender = self.sender.clone();
+            let started = job.started;
+            thread::spawn(move || {
+                // thread `first` tries first, first + step, first + 2 * step...
+                let mut nonce = first;
+                while !done.load(Ordering::Relaxed) {
+                    for _ in 0..BATCH {
+                        let hash = calculate_hash(&block, &transactions_root, nonce);
+                        if meets_difficulty(&hash, difficulty) {
+                            // only the first thread to find a nonce sends its block
+                            if !done.swap(true, Ordering::Relaxed) {
+                                let hashes = hashes.load(Ordering::Relaxed);
+                                info!(
+                                    "mined block {}, nonce: {}, {} hashes in {:?}",
+                                    block.id,
+                                    nonce,
+                                    hashes,
+                                    started.elapsed()
+                                );
+                                let mut block = (*block).clone();
+                                block.nonce = nonce;
+                                block.hash = hex::encode(hash);
+                                // the receiver is only gone when the node shuts down
+                                let _ = sender.send(block);
+                            }
+                            return;
+                        }
+                        nonce = nonce.wrapping_add(step);
+                    }
+                    hashes.fetch_add(BATCH, Ordering::Relaxed);
+                }
+            });
+        }
+        self.job = Some(job);
+    }
+
+    /// Aborts the block being mined if it's at `height` or below, as it can no longer extend
+    /// the chain once another block took its place.
+    pub fn abort(&mut self, height: u64) {
+        if let Some(job) = self.job.take_if(|job| job.height <= height) {
+            if !job.done.swap(true, Ordering::Relaxed) {
+                info!("aborted mining block {}, it has a competitor", job.height);
+            }
+        }
+    }
+
+    /// Progress of the block being mined, `None` when idle.
+    pub fn stats(&self) -> Option<MiningStats> {
+        self.job
+            .as_ref()
+            .filter(|job| !job.done.load(Ordering::Relaxed))
+            .map(Job::stats)
+    }
+}
+
+impl Drop for Miner {
+    fn drop(&mut self) {
+        if let Some(job) = &self.job {
+            job.done.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// No hash has that many leading zeros, the job only ends when aborted.
+    const UNREACHABLE: u32 = 256;
+
+    fn block(id: u64) -> Block {
+        Block::new(
+            id,
+            String::new(),
+            "mined".to_string(),
+            String::new(),
+            vec![],
+            String::new(),
+        )
+    }
+
+    /// Hashes counted by the job mining now.
+    fn hashes(miner: &Miner) -> Arc<AtomicU64> {
+        miner.job.as_ref().unwrap().hashes.clone()
+    }
+
+    #[test]
+    fn a_single_block_meeting_the_difficulty_is_sent() {
+        let (sender, mut blocks) = mpsc::unbounded_channel();
+        // an easy difficulty on many threads, so several of them find a nonce
+        let mut miner = Miner::new(8, sender);
+        miner.start(block(1), 4);
+
+        let mined = blocks.blocking_recv().unwrap();
+        let hash = hex::decode(&mined.hash).unwrap();
+        assert!(meets_difficulty(&hash, 4));
+        assert_eq!(
+            hash,
+            calculate_hash(&mined, &mined.transactions_root(), mined.nonce)
+        );
+        assert!(miner.stats().is_none());
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(blocks.try_recv().is_err());
+    }
+
+    #[test]
+    fn abort_stops_the_block_at_or_below_the_height() {
+        let (sender, _blocks) = mpsc::unbounded_channel();
+        let mut miner = Miner::new(2, sender);
+        miner.start(block(5), UNREACHABLE);
+
+        // a block below can't replace the one being mined
+        miner.abort(4);
+        assert_eq!(miner.stats().unwrap().height, 5);
+
+        let counter = hashes(&miner);
+        miner.abort(5);
+        assert!(miner.stats().is_none());
+        // the threads see it at their next batch at the latest
+        thread::sleep(Duration::from_millis(100));
+        let stopped = counter.load(Ordering::Relaxed);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(counter.load(Ordering::Relaxed), stopped);
+
+        miner.start(block(5), UNREACHABLE);
+        miner.abort(6);
+        assert!(miner.stats().is_none());
+    }
+
+    #[test]
+    fn stats_count_the_hashes_tried() {
+        let (sender, _blocks) = mpsc::unbounded_channel();
+        let mut miner = Miner::new(2, sender);
+        assert!(miner.stats().is_none());
+        miner.start(block(3), UNREACHABLE);
+
+        thread::sleep(Duration::from_millis(200));
+        let stats = miner.stats().unwrap();
+        assert_eq!(stats.height, 3);
+        assert!(stats.hashes >= BATCH);
+        assert!(stats.elapsed >= Duration::from_millis(200));
+        let expected = stats.hashes as f64 / stats.elapsed.as_secs_f64();
+        assert!((stats.hashrate() - expected).abs() < 1e-6 * expected);
+
+        let idle = MiningStats {
+            height: 3,
+            hashes: 0,
+            elapsed: Duration::ZERO,
+        };
+        assert_eq!(idle.hashrate(), 0.0);
+    }
+}
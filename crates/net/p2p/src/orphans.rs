@@ -0,0 +1,149 @@
+//! Blocks received before their parent, waiting for it to arrive.
+use std::collections::{HashMap, VecDeque};
+
+use crate::block::Block;
+
+/// Most blocks held without their parent, the oldest are dropped first.
+pub const MAX_ORPHAN_BLOCKS: usize = 256;
+
+#[derive(Debug, Default)]
+pub struct OrphanPool {
+    /// Orphans by hash.
+    blocks: HashMap<String, Block>,
+    /// Hashes of the orphans by the hash of their parent.
+    children: HashMap<String, Vec<String>>,
+    /// Hashes in the order the orphans arrived, for eviction.
+    arrival: VecDeque<String>,
+}
+
+impl OrphanPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.blocks.contains_key(hash)
+    }
+
+    /// Holds `block` until its parent arrives, dropping the oldest orphan if the pool is full.
+    pub fn insert(&mut self, block: Block) {
+        if self.contains(&block.hash) {
+            return;
+        }
+        if self.blocks.len() >= MAX_ORPHAN_BLOCKS {
+            if let Some(oldest) = self.arrival.pop_front() {
+                self.remove(&oldest);
+            }
+        }
+        self.children
+            .entry(block.previous_hash.clone())
+            .or_default()
+            .push(block.hash.clone());
+        self.arrival.push_back(block.hash.clone());
+        self.blocks.insert(block.hash.clone(), block);
+    }
+
+    /// Removes and returns the orphans whose parent is `parent_hash`.
+    pub fn take_children(&mut self, parent_hash: &str) -> Vec<Block> {
+        let hashes = self.children.remove(parent_hash).unwrap_or_default();
+        self.arrival.retain(|hash| !hashes.contains(hash));
+        hashes
+            .iter()
+            .filter_map(|hash| self.blocks.remove(hash))
+            .collect()
+    }
+
+    /// Hash of the block missing for `hash` to connect: the parent of the oldest ancestor of
+    /// `hash` in the pool.
+    pub fn missing_ancestor(&self, hash: &str) -> String {
+        let mut missing = hash;
+        while let Some(block) = self.blocks.get(missing) {
+            missing = &block.previous_hash;
+        }
+        missing.to_string()
+    }
+
+    fn remove(&mut self, hash: &str) {
+        if let Some(block) = self.blocks.remove(hash) {
+            if let Some(siblings) = self.children.get_mut(&block.previous_hash) {
+                siblings.retain(|sibling| sibling != hash);
+                if siblings.is_empty() {
+                    self.children.remove(&block.previous_hash);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(hash: &str, previous_hash: &str) -> Block {
+        let mut block = Block::new(
+            1,
+            previous_hash.to_string(),
+            String::new(),
+            String::new(),
+            vec![],
+            String::new(),
+        );
+        block.hash = hash.to_string();
+        block
+    }
+
+    fn hashes(blocks: Vec<Block>) -> Vec<String> {
+        blocks.into_iter().map(|block| block.hash).collect()
+    }
+
+    #[test]
+    fn oldest_orphans_are_evicted() {
+        let mut pool = OrphanPool::new();
+        for i in 0..=MAX_ORPHAN_BLOCKS {
+            pool.insert(block(&i.to_string(), "parent"));
+        }
+        assert_eq!(pool.len(), MAX_ORPHAN_BLOCKS);
+        assert!(!pool.contains("0"));
+        assert!(pool.contains("1"));
+        assert_eq!(pool.take_children("parent").len(), MAX_ORPHAN_BLOCKS);
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn taking_children_forgets_their_arrival() {
+        let mut pool = OrphanPool::new();
+        pool.insert(block("a", "parent"));
+        pool.insert(block("b", "parent"));
+        pool.insert(block("c", "other"));
+        assert_eq!(hashes(pool.take_children("parent")), ["a", "b"]);
+        assert!(pool.take_children("parent").is_empty());
+        assert_eq!(pool.arrival, ["c"]);
+
+        // the next eviction drops c, the oldest orphan still held
+        for i in 0..MAX_ORPHAN_BLOCKS {
+            pool.insert(block(&i.to_string(), "next"));
+        }
+        assert!(!pool.contains("c"));
+        assert!(!pool.children.contains_key("other"));
+        assert_eq!(pool.arrival.len(), pool.len());
+    }
+
+    #[test]
+    fn missing_ancestor_walks_up_the_orphans() {
+        let mut pool = OrphanPool::new();
+        pool.insert(block("c", "b"));
+        pool.insert(block("a", "root"));
+        pool.insert(block("b", "a"));
+        assert_eq!(pool.missing_ancestor("c"), "root");
+        assert_eq!(pool.missing_ancestor("a"), "root");
+        assert_eq!(pool.missing_ancestor("unknown"), "unknown");
+    }
+}
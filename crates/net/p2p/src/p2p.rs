@@ -1,4 +1,12 @@
-use crate::block::Block;
+use crate::{
+    block::Block, builder::build_block, chain::BlockImport, consensus::ProofOfAuthority,
+    finality::CheckpointVote, miner::Miner,
+};
+use felipeum_primitives::{
+    chain_spec::Engine,
+    codec::{decode, encode, expect_items, list_items, Codec, MAX_BLOCK_SIZE},
+};
+use felipeum_signature::keypair::Keypair;
 use felipeum_transaction_pool::pool::{PoolError, PoolTransaction};
 use libp2p::{
     floodsub::{Floodsub, FloodsubEvent, Topic},
@@ -7,8 +15,9 @@ use libp2p::{
     swarm::{NetworkBehaviourEventProcess, Swarm},
     NetworkBehaviour, PeerId,
 };
-use log::{error, info};
+use log::{error, info, warn};
 use once_cell::sync::Lazy;
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use tokio::sync::mpsc;
@@ -17,19 +26,135 @@ use crate::chain::Chain;
 
 pub static KEYS: Lazy<identity::Keypair> = Lazy::new(identity::Keypair::generate_ed25519);
 pub static PEER_ID: Lazy<PeerId> = Lazy::new(|| PeerId::from(KEYS.public()));
+/// Shared by all chains, peers on another chain are found out and dropped here.
 pub static CHAIN_TOPIC: Lazy<Topic> = Lazy::new(|| Topic::new("chains"));
-pub static BLOCK_TOPIC: Lazy<Topic> = Lazy::new(|| Topic::new("blocks"));
-pub static POOL_TX_TOPIC: Lazy<Topic> = Lazy::new(|| Topic::new("pool_tx"));
+
+/// Gossip topics of a chain, named after its genesis hash. Peers on another chain never
+/// subscribe to them, so their blocks and transactions don't reach us even before they're
+/// found out on the [`CHAIN_TOPIC`].
+#[derive(Clone, Debug)]
+pub struct ChainTopics {
+    pub blocks: Topic,
+    pub pool_tx: Topic,
+    pub block_requests: Topic,
+    pub checkpoints: Topic,
+}
+
+impl ChainTopics {
+    pub fn new(genesis_hash: &str) -> Self {
+        let topic = |name: &str| Topic::new(format!("{name}/{genesis_hash}"));
+        Self {
+            blocks: topic("blocks"),
+            pool_tx: topic("pool_tx"),
+            block_requests: topic("block_requests"),
+            checkpoints: topic("checkpoints"),
+        }
+    }
+}
+
+/// Largest chain a peer is allowed to send us in a single response. A chain we receive is
+/// replayed from genesis to check it, so this also bounds the work a peer can make us do.
+pub const MAX_CHAIN_RESPONSE_SIZE: usize = 4 * MAX_BLOCK_SIZE;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChainResponse {
     pub blocks: Vec<Block>,
     pub receiver: String,
+    /// Genesis hash of the sender, blocks of another chain are never looked at.
+    pub genesis_hash: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LocalChainRequest {
     pub from_peer_id: String,
+    /// Genesis hash of the requester, peers on another chain don't answer.
+    pub genesis_hash: String,
+}
+
+impl Encodable for ChainResponse {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(3);
+        s.append_list::<Block, _>(&self.blocks);
+        s.append(&self.receiver);
+        s.append(&self.genesis_hash);
+    }
+}
+
+impl Decodable for ChainResponse {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let items = expect_items(rlp, 3)?;
+        let blocks = list_items(&items[0])?;
+        if blocks
+            .iter()
+            .any(|block| block.as_raw().len() > MAX_BLOCK_SIZE)
+        {
+            return Err(DecoderError::Custom("block exceeds the block size limit"));
+        }
+        Ok(Self {
+            blocks: blocks
+                .iter()
+                .map(|block| block.as_val())
+                .collect::<Result<_, _>>()?,
+            receiver: items[1].as_val()?,
+            genesis_hash: items[2].as_val()?,
+        })
+    }
+}
+
+impl Codec for ChainResponse {
+    const MAX_SIZE: usize = MAX_CHAIN_RESPONSE_SIZE;
+}
+
+impl Encodable for LocalChainRequest {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(2);
+        s.append(&self.from_peer_id);
+        s.append(&self.genesis_hash);
+    }
+}
+
+impl Decodable for LocalChainRequest {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let items = expect_items(rlp, 2)?;
+        Ok(Self {
+            from_peer_id: items[0].as_val()?,
+            genesis_hash: items[1].as_val()?,
+        })
+    }
+}
+
+impl Codec for LocalChainRequest {
+    const MAX_SIZE: usize = 1024;
+}
+
+/// Asks `receiver` for the block `hash`, the missing parent of an orphan it sent us. It's
+/// answered on the [`ChainTopics::blocks`] topic like any new block.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlockRequest {
+    pub hash: String,
+    pub receiver: String,
+}
+
+impl Encodable for BlockRequest {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(2);
+        s.append(&self.hash);
+        s.append(&self.receiver);
+    }
+}
+
+impl Decodable for BlockRequest {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let items = expect_items(rlp, 2)?;
+        Ok(Self {
+            hash: items[0].as_val()?,
+            receiver: items[1].as_val()?,
+        })
+    }
+}
+
+impl Codec for BlockRequest {
+    const MAX_SIZE: usize = 1024;
 }
 
 pub enum EventType {
@@ -37,9 +162,13 @@ pub enum EventType {
     Input(String),
     Init,
     NewTx(PoolTransaction),
+    Mined(Block),
+    BuildBlock,
+    ImportFutureBlocks,
 }
 
 #[derive(NetworkBehaviour)]
+#[behaviour(event_process = true)]
 pub struct AppBehaviour {
     pub floodsub: Floodsub,
     pub mdns: Mdns,
@@ -49,64 +178,229 @@ pub struct AppBehaviour {
     pub init_sender: mpsc::UnboundedSender<bool>,
     #[behaviour(ignore)]
     pub app: Chain,
+    /// Address paid for the blocks mined by this node, it doesn't mine without one.
+    #[behaviour(ignore)]
+    pub miner_address: Option<String>,
+    #[behaviour(ignore)]
+    pub miner: Miner,
+    /// Key of this node if it's a validator, sealing its blocks under proof of authority.
+    #[behaviour(ignore)]
+    pub signer: Option<Keypair>,
+    #[behaviour(ignore)]
+    pub topics: ChainTopics,
+    /// Peers found to be on a chain with another genesis block, never added back.
+    #[behaviour(ignore)]
+    pub incompatible_peers: HashSet<PeerId>,
+    /// Height and hash of the latest checkpoint this node signed as a validator. A reorg may put
+    /// another block at the same height, which is signed again.
+    #[behaviour(ignore)]
+    pub last_checkpoint_vote: Option<(u64, String)>,
 }
 
 impl AppBehaviour {
     pub async fn new(
         app: Chain,
+        miner_address: Option<String>,
+        miner: Miner,
+        signer: Option<Keypair>,
         response_sender: mpsc::UnboundedSender<ChainResponse>,
         init_sender: mpsc::UnboundedSender<bool>,
     ) -> Self {
+        let topics = ChainTopics::new(app.genesis_hash());
         let mut behaviour = Self {
             app,
+            miner_address,
+            miner,
+            signer,
             floodsub: Floodsub::new(*PEER_ID),
             mdns: Mdns::new(Default::default())
                 .await
                 .expect("can create mdns"),
             response_sender,
             init_sender,
+            topics: topics.clone(),
+            incompatible_peers: HashSet::new(),
+            last_checkpoint_vote: None,
         };
         behaviour.floodsub.subscribe(CHAIN_TOPIC.clone());
-        behaviour.floodsub.subscribe(BLOCK_TOPIC.clone());
-        behaviour.floodsub.subscribe(POOL_TX_TOPIC.clone());
+        behaviour.floodsub.subscribe(topics.blocks);
+        behaviour.floodsub.subscribe(topics.pool_tx);
+        behaviour.floodsub.subscribe(topics.block_requests);
+        behaviour.floodsub.subscribe(topics.checkpoints);
 
         behaviour
     }
+
+    /// Stops talking to `peer` if it's on another chain, returns whether it is.
+    fn reject_incompatible(&mut self, peer: PeerId, genesis_hash: &str) -> bool {
+        if genesis_hash == self.app.genesis_hash() {
+            return false;
+        }
+        warn!(
+            "peer {} has genesis {}, ours is {}, disconnecting",
+            peer,
+            genesis_hash,
+            self.app.genesis_hash()
+        );
+        self.floodsub.remove_node_from_partial_view(&peer);
+        self.incompatible_peers.insert(peer);
+        true
+    }
+
+    /// Signs the latest checkpoint and broadcasts the vote, if this node is a validator under
+    /// proof of authority that didn't sign it yet.
+    fn vote_checkpoint(&mut self) {
+        let (Some(signer), Engine::ProofOfAuthority { validators }) =
+            (&self.signer, &self.app.spec.engine)
+        else {
+            return;
+        };
+        if !validators.contains(&hex::encode(signer.public_key())) {
+            return;
+        }
+        let latest = self
+            .app
+            .blocks
+            .last()
+            .expect("there is at least one block")
+            .id;
+        let height = latest - latest % self.app.spec.finality.checkpoint_interval;
+        if height <= self.app.finalized_block().id {
+            return;
+        }
+        let checkpoint = &self.app.blocks[height as usize];
+        let key = (height, checkpoint.hash.clone());
+        if self.last_checkpoint_vote.as_ref() == Some(&key) {
+            return;
+        }
+        let vote = CheckpointVote::sign(checkpoint, signer);
+        info!("signing checkpoint {}", height);
+        self.last_checkpoint_vote = Some(key);
+        let encoded = encode(&vote);
+        self.app.add_checkpoint_vote(vote);
+        self.floodsub
+            .publish(self.topics.checkpoints.clone(), encoded);
+    }
 }
 
 // incoming event handler
 impl NetworkBehaviourEventProcess<FloodsubEvent> for AppBehaviour {
     fn inject_event(&mut self, event: FloodsubEvent) {
         if let FloodsubEvent::Message(msg) = event {
-            if let Ok(resp) = serde_json::from_slice::<ChainResponse>(&msg.data) {
-                if resp.receiver == PEER_ID.to_string() {
-                    info!("Response from {}:", msg.source);
-                    resp.blocks.iter().for_each(|r| info!("{:?}", r));
+            if self.incompatible_peers.contains(&msg.source) {
+                return;
+            }
+            if msg.topics.contains(&CHAIN_TOPIC) {
+                if let Ok(resp) = decode::<ChainResponse>(&msg.data) {
+                    if resp.receiver == PEER_ID.to_string()
+                        && !self.reject_incompatible(msg.source, &resp.genesis_hash)
+                    {
+                        info!("Response from {}:", msg.source);
+                        resp.blocks.iter().for_each(|r| info!("{:?}", r));
 
-                    self.app.blocks = self.app.choose_chain(self.app.blocks.clone(), resp.blocks);
+                        self.app.blocks =
+                            self.app.choose_chain(self.app.blocks.clone(), resp.blocks);
+                        self.app.update_finality();
+                        let latest_block =
+                            self.app.blocks.last().expect("there is at least one block");
+                        self.miner.abort(latest_block.id);
+                        self.vote_checkpoint();
+                    }
+                } else if let Ok(resp) = decode::<LocalChainRequest>(&msg.data) {
+                    info!("sending local chain to {}", msg.source.to_string());
+                    let peer_id = resp.from_peer_id;
+                    if PEER_ID.to_string() == peer_id
+                        && !self.reject_incompatible(msg.source, &resp.genesis_hash)
+                    {
+                        if let Err(e) = self.response_sender.send(ChainResponse {
+                            blocks: self.app.blocks.clone(),
+                            receiver: msg.source.to_string(),
+                            genesis_hash: self.app.genesis_hash().to_string(),
+                        }) {
+                            error!("error sending response via channel, {}", e);
+                        }
+                    }
+                } else {
+                    warn!("invalid chain message from {}", msg.source.to_string());
+                }
+            } else if msg.topics.contains(&self.topics.blocks) {
+                match decode::<Block>(&msg.data) {
+                    Ok(block) => {
+                        info!("received new block from {}", msg.source.to_string());
+                        match self.app.import_block(block) {
+                            BlockImport::Added => {
+                                // orphans waiting for the block may have been added with it
+                                let latest_block =
+                                    self.app.blocks.last().expect("there is at least one block");
+                                self.miner.abort(latest_block.id);
+                                self.vote_checkpoint();
+                            }
+                            BlockImport::Orphan(missing) => {
+                                info!("requesting block {} from {}", missing, msg.source);
+                                let request = BlockRequest {
+                                    hash: missing,
+                                    receiver: msg.source.to_string(),
+                                };
+                                self.floodsub
+                                    .publish(self.topics.block_requests.clone(), encode(&request));
+                            }
+                            BlockImport::Future | BlockImport::Rejected => {}
+                        }
+                    }
+                    Err(err) => warn!("invalid block from {}: {}", msg.source.to_string(), err),
                 }
-            } else if let Ok(resp) = serde_json::from_slice::<LocalChainRequest>(&msg.data) {
-                info!("sending local chain to {}", msg.source.to_string());
-                let peer_id = resp.from_peer_id;
-                if PEER_ID.to_string() == peer_id {
-                    if let Err(e) = self.response_sender.send(ChainResponse {
-                        blocks: self.app.blocks.clone(),
-                        receiver: msg.source.to_string(),
-                    }) {
-                        error!("error sending response via channel, {}", e);
+            } else if msg.topics.contains(&self.topics.block_requests) {
+                match decode::<BlockRequest>(&msg.data) {
+                    Ok(request) if request.receiver == PEER_ID.to_string() => {
+                        if let Some(block) = self
+                            .app
+                            .blocks
+                            .iter()
+                            .find(|block| block.hash == request.hash)
+                        {
+                            info!("sending block {} to {}", block.id, msg.source);
+                            let encoded = encode(block);
+                            self.floodsub.publish(self.topics.blocks.clone(), encoded);
+                        }
                     }
+                    Ok(_) => {}
+                    Err(err) => warn!(
+                        "invalid block request from {}: {}",
+                        msg.source.to_string(),
+                        err
+                    ),
                 }
-            } else if let Ok(block) = serde_json::from_slice::<Block>(&msg.data) {
-                info!("received new block from {}", msg.source.to_string());
-                self.app.try_add_block(block);
-            } else if let Ok(tx) = serde_json::from_slice::<PoolTransaction>(&msg.data) {
-                info!(
-                    "received new pool transaction from {}",
-                    msg.source.to_string(),
-                );
-                match self.app.add_new_pool_transaction(tx) {
-                    Ok(added) => info!("added new pool transaction {:?}", added),
-                    Err(err) => error!("error adding new pool transaction {:?}", err),
+            } else if msg.topics.contains(&self.topics.checkpoints) {
+                match decode::<CheckpointVote>(&msg.data) {
+                    Ok(vote) => {
+                        let height = vote.height;
+                        if self.app.add_checkpoint_vote(vote) {
+                            info!("checkpoint vote for {} from {}", height, msg.source);
+                        }
+                    }
+                    Err(err) => warn!(
+                        "invalid checkpoint vote from {}: {}",
+                        msg.source.to_string(),
+                        err
+                    ),
+                }
+            } else if msg.topics.contains(&self.topics.pool_tx) {
+                match decode::<PoolTransaction>(&msg.data) {
+                    Ok(tx) => {
+                        info!(
+                            "received new pool transaction from {}",
+                            msg.source.to_string(),
+                        );
+                        match self.app.add_new_pool_transaction(tx) {
+                            Ok(added) => info!("added new pool transaction {:?}", added),
+                            Err(err) => error!("error adding new pool transaction {:?}", err),
+                        }
+                    }
+                    Err(err) => warn!(
+                        "invalid pool transaction from {}: {}",
+                        msg.source.to_string(),
+                        err
+                    ),
                 }
             }
         }
@@ -118,7 +412,9 @@ impl NetworkBehaviourEventProcess<MdnsEvent> for AppBehaviour {
         match event {
             MdnsEvent::Discovered(discovered_list) => {
                 for (peer, _addr) in discovered_list {
-                    self.floodsub.add_node_to_partial_view(peer);
+                    if !self.incompatible_peers.contains(&peer) {
+                        self.floodsub.add_node_to_partial_view(peer);
+                    }
                 }
             }
             MdnsEvent::Expired(expired_list) => {
@@ -134,9 +430,10 @@ impl NetworkBehaviourEventProcess<MdnsEvent> for AppBehaviour {
 
 pub fn get_list_peers(swarm: &Swarm<AppBehaviour>) -> Vec<String> {
     info!("Discovered Peers:");
-    let nodes = swarm.behaviour().mdns.discovered_nodes();
+    let behaviour = swarm.behaviour();
+    let nodes = behaviour.mdns.discovered_nodes();
     let mut unique_peers = HashSet::new();
-    for peer in nodes {
+    for peer in nodes.filter(|peer| !behaviour.incompatible_peers.contains(*peer)) {
         unique_peers.insert(peer);
     }
     unique_peers.iter().map(|p| p.to_string()).collect()
@@ -154,6 +451,19 @@ pub fn handle_print_chain(swarm: &Swarm<AppBehaviour>) {
     info!("{}", pretty_json);
 }
 
+pub fn handle_print_mining(swarm: &Swarm<AppBehaviour>) {
+    match swarm.behaviour().miner.stats() {
+        Some(stats) => info!(
+            "mining block {}: {} hashes in {:?}, {:.0} H/s",
+            stats.height,
+            stats.hashes,
+            stats.elapsed,
+            stats.hashrate()
+        ),
+        None => info!("not mining"),
+    }
+}
+
 pub fn handle_new_pool_transaction(
     tx: PoolTransaction,
     swarm: &mut Swarm<AppBehaviour>,
@@ -162,26 +472,289 @@ pub fn handle_new_pool_transaction(
     behaviour.app.add_new_pool_transaction(tx)
 }
 
-// TODO: we don't need this, but keep it for now for when we actually need to broadcast a block
-// we can look at it as an example
+/// Produces a block with the payload of `cmd`: mines it under proof of work, it's broadcast by
+/// [`handle_mined_block`], or seals and broadcasts it right away under proof of authority.
 pub fn handle_create_block(cmd: &str, swarm: &mut Swarm<AppBehaviour>) {
     if let Some(data) = cmd.strip_prefix("create b") {
-        let behaviour = swarm.behaviour_mut();
+        produce_block(swarm.behaviour_mut(), data.to_owned());
+    }
+}
+
+/// Called every block time. A miner that isn't mining already mines the transactions waiting in
+/// the pool, if there are any. A validator seals a block whenever it's its turn, even an empty
+/// one, so the turn moves on to the next validator.
+pub fn handle_build_block(swarm: &mut Swarm<AppBehaviour>) {
+    let behaviour = swarm.behaviour_mut();
+    let ready = match &behaviour.app.spec.engine {
+        Engine::ProofOfWork => {
+            behaviour.miner_address.is_some()
+                && behaviour.miner.stats().is_none()
+                && !behaviour.app.pool.get_all().is_empty()
+        }
+        Engine::ProofOfAuthority { validators } => {
+            let height = behaviour
+                .app
+                .blocks
+                .last()
+                .expect("there is at least one block")
+                .id
+                + 1;
+            let engine = ProofOfAuthority {
+                validators: validators.clone(),
+            };
+            behaviour
+                .signer
+                .as_ref()
+                .is_some_and(|signer| hex::encode(signer.public_key()) == engine.in_turn(height))
+        }
+    };
+    if ready {
+        produce_block(behaviour, String::new());
+    }
+}
+
+fn produce_block(behaviour: &mut AppBehaviour, data: String) {
+    match behaviour.app.spec.engine.clone() {
+        Engine::ProofOfWork => {
+            let beneficiary = match &behaviour.miner_address {
+                Some(address) => address,
+                None => {
+                    error!("no miner address to pay, start the node with --miner-address");
+                    return;
+                }
+            };
+            let block = match build_block(&behaviour.app, beneficiary, data) {
+                Ok(block) => block,
+                Err(err) => {
+                    error!("can't build block: {}", err);
+                    return;
+                }
+            };
+            info!(
+                "built block {} with {} transactions",
+                block.id,
+                block.transactions.len()
+            );
+            let difficulty = behaviour.app.spec.difficulty;
+            behaviour.miner.start(block, difficulty);
+        }
+        Engine::ProofOfAuthority { validators } => {
+            let signer = match &behaviour.signer {
+                Some(signer) => signer,
+                None => {
+                    error!("not a validator, start the node with --signer");
+                    return;
+                }
+            };
+            // validators are paid themselves unless told otherwise
+            let beneficiary = behaviour
+                .miner_address
+                .clone()
+                .unwrap_or_else(|| hex::encode(signer.public_key()));
+            let mut block = match build_block(&behaviour.app, &beneficiary, data) {
+                Ok(block) => block,
+                Err(err) => {
+                    error!("can't build block: {}", err);
+                    return;
+                }
+            };
+            if let Err(err) = (ProofOfAuthority { validators }).seal(&mut block, signer) {
+                error!("can't seal block {}: {}", block.id, err);
+                return;
+            }
+            info!(
+                "sealed block {} with {} transactions",
+                block.id,
+                block.transactions.len()
+            );
+            import_and_broadcast(behaviour, block);
+        }
+    }
+}
+
+/// Adds the blocks from peers that were held until their timestamp was reached.
+pub fn handle_import_future_blocks(swarm: &mut Swarm<AppBehaviour>) {
+    let behaviour = swarm.behaviour_mut();
+    if behaviour.app.import_future_blocks() > 0 {
         let latest_block = behaviour
             .app
             .blocks
             .last()
             .expect("there is at least one block");
-        let block = Block::new(
-            latest_block.id + 1,
-            latest_block.hash.clone(),
-            data.to_owned(),
-        );
-        let json = serde_json::to_string(&block).expect("can jsonify request");
-        behaviour.app.blocks.push(block);
+        behaviour.miner.abort(latest_block.id);
+        behaviour.vote_checkpoint();
+    }
+}
+
+/// Imports a block found by the [`Miner`] and broadcasts it, unless the chain moved on while it
+/// was being mined.
+pub fn handle_mined_block(block: Block, swarm: &mut Swarm<AppBehaviour>) {
+    import_and_broadcast(swarm.behaviour_mut(), block);
+}
+
+fn import_and_broadcast(behaviour: &mut AppBehaviour, block: Block) {
+    let encoded = encode(&block);
+    if behaviour.app.try_add_block(block) {
         info!("broadcasting new block");
-        behaviour
-            .floodsub
-            .publish(BLOCK_TOPIC.clone(), json.as_bytes());
+        let topic = behaviour.topics.blocks.clone();
+        behaviour.floodsub.publish(topic, encoded);
+        behaviour.vote_checkpoint();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use felipeum_primitives::{chain_spec::ChainSpec, codec::CodecError};
+    use felipeum_transaction_pool::pool::Pool;
+    use libp2p::floodsub::FloodsubMessage;
+
+    async fn node(
+        chain: Chain,
+        miner_address: Option<String>,
+        signer: Option<Keypair>,
+    ) -> (AppBehaviour, mpsc::UnboundedReceiver<ChainResponse>) {
+        let (response_sender, responses) = mpsc::unbounded_channel();
+        let (init_sender, _) = mpsc::unbounded_channel();
+        let (mined_sender, _) = mpsc::unbounded_channel();
+        let behaviour = AppBehaviour::new(
+            chain,
+            miner_address,
+            Miner::new(1, mined_sender),
+            signer,
+            response_sender,
+            init_sender,
+        )
+        .await;
+        (behaviour, responses)
+    }
+
+    fn response(data: &[&str]) -> ChainResponse {
+        ChainResponse {
+            blocks: data
+                .iter()
+                .enumerate()
+                .map(|(id, data)| {
+                    Block::new(
+                        id as u64,
+                        String::new(),
+                        data.to_string(),
+                        String::new(),
+                        vec![],
+                        String::new(),
+                    )
+                })
+                .collect(),
+            receiver: "peer".to_string(),
+            genesis_hash: "genesis".to_string(),
+        }
+    }
+
+    #[test]
+    fn chain_responses_bound_each_block() {
+        let small = response(&["a", "b"]);
+        let decoded = decode::<ChainResponse>(&encode(&small)).unwrap();
+        assert_eq!(decoded.blocks.len(), 2);
+        assert_eq!(decoded.genesis_hash, small.genesis_hash);
+
+        let large = "x".repeat(MAX_BLOCK_SIZE);
+        assert!(matches!(
+            decode::<ChainResponse>(&encode(&response(&["a", &large]))),
+            Err(CodecError::Malformed(_))
+        ));
+
+        let most = "x".repeat(MAX_BLOCK_SIZE / 2);
+        assert!(matches!(
+            decode::<ChainResponse>(&encode(&response(&[most.as_str(); 9]))),
+            Err(CodecError::TooLarge { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn peers_on_another_chain_are_refused() {
+        let (mut behaviour, mut responses) =
+            node(Chain::new(Pool::new(), ChainSpec::local()), None, None).await;
+        let mut spec = ChainSpec::local();
+        spec.genesis.extra_data = "another chain".to_string();
+        let other = Chain::new(Pool::new(), spec);
+        let message = |source: PeerId, data: Vec<u8>| {
+            FloodsubEvent::Message(FloodsubMessage {
+                source,
+                data,
+                sequence_number: vec![],
+                topics: vec![CHAIN_TOPIC.clone()],
+            })
+        };
+
+        // their gossip never reaches us, the topics differ
+        let topics = ChainTopics::new(other.genesis_hash());
+        assert_ne!(topics.blocks, behaviour.topics.blocks);
+        assert_ne!(topics.pool_tx, behaviour.topics.pool_tx);
+
+        // a peer on our chain is answered
+        let peer = PeerId::random();
+        let request = LocalChainRequest {
+            from_peer_id: PEER_ID.to_string(),
+            genesis_hash: behaviour.app.genesis_hash().to_string(),
+        };
+        behaviour.inject_event(message(peer, encode(&request)));
+        assert_eq!(responses.try_recv().unwrap().receiver, peer.to_string());
+        assert!(behaviour.incompatible_peers.is_empty());
+
+        // one asking for our chain with another genesis isn't
+        let stranger = PeerId::random();
+        let request = LocalChainRequest {
+            from_peer_id: PEER_ID.to_string(),
+            genesis_hash: other.genesis_hash().to_string(),
+        };
+        behaviour.inject_event(message(stranger, encode(&request)));
+        assert!(responses.try_recv().is_err());
+        assert!(behaviour.incompatible_peers.contains(&stranger));
+
+        // and the chain of another one is never looked at
+        let stranger = PeerId::random();
+        let response = ChainResponse {
+            blocks: other.blocks.clone(),
+            receiver: PEER_ID.to_string(),
+            genesis_hash: other.genesis_hash().to_string(),
+        };
+        behaviour.inject_event(message(stranger, encode(&response)));
+        assert!(behaviour.incompatible_peers.contains(&stranger));
+        assert_eq!(behaviour.app.blocks.len(), 1);
+        assert_ne!(behaviour.app.genesis_hash(), other.genesis_hash());
+    }
+
+    #[tokio::test]
+    async fn validators_are_paid_themselves_without_a_miner_address() {
+        let validator_chain = |signer: &Keypair| {
+            let mut spec = ChainSpec::local();
+            spec.engine = Engine::ProofOfAuthority {
+                validators: vec![hex::encode(signer.public_key())],
+            };
+            Chain::new(Pool::new(), spec)
+        };
+        let reward = ChainSpec::local().reward.at(1);
+
+        let signer = Keypair::new();
+        let validator = hex::encode(signer.public_key());
+        let chain = validator_chain(&signer);
+        let (mut behaviour, _) = node(chain, None, Some(signer)).await;
+        produce_block(&mut behaviour, String::new());
+        let block = behaviour.app.blocks.last().unwrap();
+        assert_eq!(block.id, 1);
+        assert_eq!(block.beneficiary, validator);
+        assert_eq!(behaviour.app.state.balance(&validator), reward);
+
+        // unless they're told to pay someone else
+        let signer = Keypair::new();
+        let validator = hex::encode(signer.public_key());
+        let payee = hex::encode(Keypair::new().public_key());
+        let chain = validator_chain(&signer);
+        let (mut behaviour, _) = node(chain, Some(payee.clone()), Some(signer)).await;
+        produce_block(&mut behaviour, String::new());
+        let block = behaviour.app.blocks.last().unwrap();
+        assert_eq!(block.beneficiary, payee);
+        assert_eq!(behaviour.app.state.balance(&payee), reward);
+        assert!(behaviour.app.state.balance(&validator).is_zero());
     }
 }
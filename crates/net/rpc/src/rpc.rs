@@ -1,8 +1,11 @@
 use felipeum_primitives::{
-    signature::Signature,
-    transaction::{Transaction, TransactionSigned},
+    amount::U256,
+    block_tag::{BlockRef, BlockTag, BlockTags},
+    receipt::{Receipt, ReceiptStore},
+    signature::{parse_address, verify_message, Signature, TransactionSignature},
+    transaction::{Timelock, Transaction, TransactionKind, TransactionSigned, Transfer},
 };
-use felipeum_signature::keypair::new_keypair;
+use felipeum_signature::{keypair::Keypair, keystore::Keystore};
 use felipeum_transaction_pool::pool::{Pool, PoolTransaction};
 use jsonrpsee::proc_macros::rpc;
 use jsonrpsee::server::ServerBuilder;
@@ -13,14 +16,26 @@ use jsonrpsee::{
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
 use tower_http::cors::{Any, CorsLayer};
+use zeroize::Zeroizing;
 
 #[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct InnerTransactionRequest {
     pub from: String,
+    #[serde(default)]
     pub to: String,
-    pub value: u64,
+    #[serde(default)]
+    pub value: U256,
     pub nonce: u64,
+    #[serde(default)]
+    pub fee: U256,
+    /// Sends these transfers as one atomic batch instead of the single `to`/`value` transfer.
+    #[serde(default)]
+    pub batch: Option<Vec<Transfer>>,
+    #[serde(default)]
+    pub valid_after: Option<Timelock>,
+    #[serde(default)]
+    pub valid_until: Option<Timelock>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
@@ -35,41 +50,72 @@ pub trait RpcSpec {
     #[method(name = "sendTransaction")]
     async fn send_transaction(&self, tx: TransactionRequest) -> RpcResult<String>;
 
+    /// Creates an account in the node's keystore, encrypted with `passphrase`, and returns its
+    /// address. The key never leaves the node, back it up with
+    /// `felipeum-keygen keystore export`.
     #[method(name = "newAccount")]
-    async fn new_account(&self) -> RpcResult<NewAccount>;
+    async fn new_account(&self, passphrase: String) -> RpcResult<String>;
+
+    /// Checks `signature` is the signature of `message` by the account at `address`, of either
+    /// signature scheme. Messages are signed offline, e.g. with `felipeum-keygen sign`, so keys
+    /// never go over the wire.
+    #[method(name = "verifyMessage")]
+    async fn verify_message(
+        &self,
+        address: String,
+        message: String,
+        signature: String,
+    ) -> RpcResult<bool>;
+
+    /// Number, hash and timestamp of the `latest`, `safe` or `finalized` block.
+    #[method(name = "getBlockByTag")]
+    async fn get_block_by_tag(&self, tag: BlockTag) -> RpcResult<BlockRef>;
+
+    /// Receipt of the transaction `hash`, `null` until it's included in a block.
+    #[method(name = "getTransactionReceipt")]
+    async fn get_transaction_receipt(&self, hash: String) -> RpcResult<Option<Receipt>>;
 }
 
 struct RpcServer {
     transaction_pool: Pool,
-}
-
-#[derive(Serialize, Deserialize, Clone, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct NewAccount {
-    pub public_key: String,
-    pub private_key: String,
+    block_tags: BlockTags,
+    receipts: ReceiptStore,
+    keystore: Keystore,
 }
 
 #[async_trait]
 impl RpcSpecServer for RpcServer {
     async fn send_transaction(&self, tx: TransactionRequest) -> RpcResult<String> {
+        let kind = match tx.transaction.batch {
+            Some(transfers) => TransactionKind::Batch(transfers),
+            None => TransactionKind::Transfer(Transfer {
+                to: tx.transaction.to,
+                value: tx.transaction.value,
+            }),
+        };
         let transaction = Transaction {
             from: tx.transaction.from,
-            to: tx.transaction.to,
             nonce: tx.transaction.nonce,
+            fee: tx.transaction.fee,
+            kind,
+            valid_after: tx.transaction.valid_after,
+            valid_until: tx.transaction.valid_until,
         };
         info!("transaction: {:?}", transaction);
+        // the signature covers the addresses, so they can't be lowercased for the sender
+        if !transaction.has_lowercase_addresses() {
+            return Err(Error::Custom("addresses must be lowercase".to_string()));
+        }
 
-        // let encoded = transaction.signature_hash();
-        // info!("encoded: {:?}", encoded);
-
-        // let hash = std::str::from_utf8(&encoded).unwrap().to_string();
-        // info!("hash: {:?}", hash);
-
-        // let signature = Signature::new(tx.signature.as_bytes());
-        // info!("signature: {:?}", signature);
-        let signature = Signature::new("asdf".as_bytes());
-        let hash = "asdf".to_string();
+        let signature = decode_signature(&tx.signature)?;
+        let hash = transaction.signature_hash();
+        if !signature.verify_address(&transaction.from, &hash) {
+            return Err(Error::Custom(
+                "signature does not match the transaction".to_string(),
+            ));
+        }
+        let signature = TransactionSignature::Single(signature);
+        let hash = hex::encode(hash);
 
         let transaction_signed = TransactionSigned {
             transaction,
@@ -87,25 +133,71 @@ impl RpcSpecServer for RpcServer {
         }
     }
 
-    async fn new_account(&self) -> RpcResult<NewAccount> {
-        match new_keypair() {
-            Ok(k) => Ok(NewAccount {
-                public_key: hex::encode(k.public_key()),
-                private_key: hex::encode(k.secret()),
-            }),
-
-            Err(msg) => Err(Error::Custom(msg.to_string())),
+    async fn new_account(&self, passphrase: String) -> RpcResult<String> {
+        if passphrase.is_empty() {
+            return Err(Error::Custom("passphrase can't be empty".to_string()));
         }
+        let passphrase = Zeroizing::new(passphrase);
+        let keystore = self.keystore.clone();
+        // deriving the encryption key takes a while on purpose, it mustn't block the server
+        tokio::task::spawn_blocking(move || keystore.insert(&Keypair::new(), &passphrase))
+            .await
+            .map_err(|err| Error::Custom(err.to_string()))?
+            .map_err(|err| Error::Custom(err.to_string()))
+    }
+
+    async fn verify_message(
+        &self,
+        address: String,
+        message: String,
+        signature: String,
+    ) -> RpcResult<bool> {
+        let address = parse_address(&address)
+            .ok_or_else(|| Error::Custom(format!("invalid address {address}")))?;
+        let signature = decode_signature(&signature)?;
+        Ok(verify_message(&address, message.as_bytes(), &signature))
+    }
+
+    async fn get_block_by_tag(&self, tag: BlockTag) -> RpcResult<BlockRef> {
+        Ok(self.block_tags.get(tag))
     }
+
+    async fn get_transaction_receipt(&self, hash: String) -> RpcResult<Option<Receipt>> {
+        Ok(self.receipts.get(&hash))
+    }
+}
+
+fn decode_hex(field: &str, value: &str) -> RpcResult<Vec<u8>> {
+    hex::decode(value).map_err(|err| Error::Custom(format!("invalid {field}: {err}")))
+}
+
+fn decode_signature(signature: &str) -> RpcResult<Signature> {
+    Signature::from_bytes(&decode_hex("signature", signature)?)
+        .ok_or_else(|| Error::Custom("invalid signature length".to_string()))
 }
 
 impl RpcServer {
-    pub fn new(transaction_pool: Pool) -> Self {
-        RpcServer { transaction_pool }
+    pub fn new(
+        transaction_pool: Pool,
+        block_tags: BlockTags,
+        receipts: ReceiptStore,
+        keystore: Keystore,
+    ) -> Self {
+        RpcServer {
+            transaction_pool,
+            block_tags,
+            receipts,
+            keystore,
+        }
     }
 }
 
-pub async fn run_server(transaction_pool: Pool) -> anyhow::Result<SocketAddr> {
+pub async fn run_server(
+    transaction_pool: Pool,
+    block_tags: BlockTags,
+    receipts: ReceiptStore,
+    keystore: Keystore,
+) -> anyhow::Result<SocketAddr> {
     let cors = CorsLayer::new()
         .allow_methods(Any)
         .allow_origin(Any)
@@ -117,7 +209,7 @@ pub async fn run_server(transaction_pool: Pool) -> anyhow::Result<SocketAddr> {
         .build("127.0.0.1:4500")
         .await?;
 
-    let rpc_server = RpcServer::new(transaction_pool);
+    let rpc_server = RpcServer::new(transaction_pool, block_tags, receipts, keystore);
     let addr = server.local_addr()?;
     let handle = server.start(rpc_server.into_rpc())?;
 
@@ -125,3 +217,34 @@ pub async fn run_server(transaction_pool: Pool) -> anyhow::Result<SocketAddr> {
 
     Ok(addr)
 }
+
+#[cfg(test)]
+mod tests {
+    use felipeum_signature::keystore::KdfParams;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn new_accounts_stay_in_the_keystore() {
+        let dir = tempfile::tempdir().unwrap();
+        let kdf_params = KdfParams {
+            log_n: 4,
+            r: 8,
+            p: 1,
+        };
+        let keystore = Keystore::with_kdf_params(dir.path(), kdf_params).unwrap();
+        let server = RpcServer::new(
+            Pool::new(),
+            BlockTags::new(),
+            ReceiptStore::new(),
+            keystore.clone(),
+        );
+
+        assert!(server.new_account(String::new()).await.is_err());
+        let address = server.new_account("pass".to_string()).await.unwrap();
+        assert_eq!(keystore.list().unwrap(), vec![address.clone()]);
+        let keypair = keystore.unlock(&address, "pass").unwrap();
+        assert_eq!(hex::encode(keypair.public_key()), address);
+        assert!(keystore.unlock(&address, "wrong").is_err());
+    }
+}
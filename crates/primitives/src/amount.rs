@@ -0,0 +1,202 @@
+//! Token amounts.
+//!
+//! Amounts are counted in base units, [`FEL`] of them make one FEL. The operators of [`U256`]
+//! panic on overflow, so code handling balances uses the `checked_*` methods instead.
+// lints firing inside the code generated by `construct_uint!`
+#![allow(clippy::assign_op_pattern, clippy::manual_div_ceil)]
+
+use std::fmt;
+
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use uint::construct_uint;
+
+construct_uint! {
+    /// 256-bit unsigned integer, serialized as a decimal string or, through [`u256_hex`], as a
+    /// hex string.
+    pub struct U256(4);
+}
+
+/// Number of decimals of one FEL.
+pub const FEL_DECIMALS: usize = 18;
+
+/// One FEL in base units.
+pub const FEL: U256 = U256([1_000_000_000_000_000_000, 0, 0, 0]);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AmountError {
+    /// The input is not a decimal number.
+    Invalid(String),
+    /// The input has more than [`FEL_DECIMALS`] decimals.
+    TooPrecise(String),
+    /// The input doesn't fit in a [`U256`].
+    Overflow(String),
+}
+
+impl fmt::Display for AmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AmountError::Invalid(input) => write!(f, "invalid amount {input:?}"),
+            AmountError::TooPrecise(input) => {
+                write!(f, "amount {input:?} has more than {FEL_DECIMALS} decimals")
+            }
+            AmountError::Overflow(input) => write!(f, "amount {input:?} is too large"),
+        }
+    }
+}
+
+impl std::error::Error for AmountError {}
+
+/// Parses an amount of FEL like `"1.5"` into base units.
+pub fn parse_fel(input: &str) -> Result<U256, AmountError> {
+    let (whole, fraction) = input.split_once('.').unwrap_or((input, ""));
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if whole.is_empty() || !is_digits(whole) || !is_digits(fraction) {
+        return Err(AmountError::Invalid(input.to_string()));
+    }
+    if fraction.len() > FEL_DECIMALS {
+        return Err(AmountError::TooPrecise(input.to_string()));
+    }
+
+    let digits = format!("{whole}{fraction:0<FEL_DECIMALS$}");
+    U256::from_dec_str(&digits).map_err(|_| AmountError::Overflow(input.to_string()))
+}
+
+/// Formats base units as FEL, without trailing zeros in the decimals.
+pub fn format_fel(amount: U256) -> String {
+    let whole = amount / FEL;
+    let fraction = (amount % FEL).to_string();
+    if fraction == "0" {
+        return whole.to_string();
+    }
+
+    let fraction = format!("{fraction:0>FEL_DECIMALS$}");
+    format!("{whole}.{}", fraction.trim_end_matches('0'))
+}
+
+impl Serialize for U256 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for U256 {
+    /// Accepts a JSON number, a decimal string or a `0x` prefixed hex string.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = U256;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(
+                    f,
+                    "a non-negative integer, decimal string or 0x prefixed hex string"
+                )
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<U256, E> {
+                Ok(U256::from(value))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<U256, E> {
+                let parsed = match value.strip_prefix("0x") {
+                    Some(hex) if !hex.is_empty() => U256::from_str_radix(hex, 16).ok(),
+                    Some(_) => None,
+                    None if !value.is_empty() => U256::from_dec_str(value).ok(),
+                    None => None,
+                };
+                parsed.ok_or_else(|| E::invalid_value(de::Unexpected::Str(value), &self))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+/// Serializes a [`U256`] as a `0x` prefixed hex string, for fields declared with
+/// `#[serde(with = "u256_hex")]`. Reads whatever [`U256`] reads.
+pub mod u256_hex {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::U256;
+
+    pub fn serialize<S: Serializer>(value: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{value:#x}"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U256, D::Error> {
+        U256::deserialize(deserializer)
+    }
+}
+
+// big endian without leading zeros, like rlp encodes the builtin integers
+impl Encodable for U256 {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        let mut bytes = [0u8; 32];
+        self.to_big_endian(&mut bytes);
+        let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+        s.encoder().encode_value(&bytes[start..]);
+    }
+}
+
+impl Decodable for U256 {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        rlp.decoder().decode_value(|bytes| match bytes {
+            [0, ..] => Err(DecoderError::RlpInvalidIndirection),
+            _ if bytes.len() > 32 => Err(DecoderError::RlpIsTooBig),
+            _ => Ok(U256::from_big_endian(bytes)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fel_denomination() {
+        assert_eq!(parse_fel("1").unwrap(), FEL);
+        assert_eq!(parse_fel("1.5").unwrap(), FEL * 3 / 2);
+        assert_eq!(parse_fel("0.000000000000000001").unwrap(), U256::one());
+        assert!(matches!(
+            parse_fel("0.0000000000000000001"),
+            Err(AmountError::TooPrecise(_))
+        ));
+        assert!(matches!(parse_fel("1e18"), Err(AmountError::Invalid(_))));
+        assert!(matches!(parse_fel(".5"), Err(AmountError::Invalid(_))));
+
+        assert_eq!(format_fel(FEL * 3 / 2), "1.5");
+        assert_eq!(format_fel(FEL * 2), "2");
+        assert_eq!(format_fel(U256::one()), "0.000000000000000001");
+    }
+
+    #[test]
+    fn serde_and_rlp() {
+        let amount: U256 = serde_json::from_str("\"0x10\"").unwrap();
+        assert_eq!(amount, U256::from(16));
+        assert_eq!(serde_json::from_str::<U256>("16").unwrap(), amount);
+        assert_eq!(serde_json::from_str::<U256>("\"16\"").unwrap(), amount);
+        assert!(serde_json::from_str::<U256>("\"0x\"").is_err());
+        assert!(serde_json::from_str::<U256>("-1").is_err());
+        assert_eq!(serde_json::to_string(&amount).unwrap(), "\"16\"");
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Hex(#[serde(with = "u256_hex")] U256);
+        for (amount, hex) in [(U256::zero(), "\"0x0\""), (FEL, "\"0xde0b6b3a7640000\"")] {
+            assert_eq!(serde_json::to_string(&Hex(amount)).unwrap(), hex);
+            assert_eq!(serde_json::from_str::<Hex>(hex).unwrap(), Hex(amount));
+        }
+        assert_eq!(serde_json::from_str::<Hex>("\"16\"").unwrap(), Hex(amount));
+
+        for amount in [U256::zero(), U256::from(0x7f), FEL, U256::MAX] {
+            assert_eq!(rlp::decode::<U256>(&rlp::encode(&amount)).unwrap(), amount);
+        }
+        // same encoding as the builtin integers
+        assert_eq!(
+            rlp::encode(&FEL),
+            rlp::encode(&1_000_000_000_000_000_000u64)
+        );
+        assert!(rlp::decode::<U256>(&[0x82, 0x00, 0x01]).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+//! Blocks named by how settled they are rather than by number, kept up to date by the node and
+//! read by the RPC.
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockTag {
+    /// The latest block of the chain, it may still be replaced by a competing block.
+    Latest,
+    /// Deep enough that replacing it is unlikely, though not impossible.
+    Safe,
+    /// Never reverted, the node refuses chains that don't contain it.
+    Finalized,
+}
+
+impl fmt::Display for BlockTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockTag::Latest => write!(f, "latest"),
+            BlockTag::Safe => write!(f, "safe"),
+            BlockTag::Finalized => write!(f, "finalized"),
+        }
+    }
+}
+
+impl FromStr for BlockTag {
+    type Err = String;
+
+    fn from_str(tag: &str) -> Result<Self, Self::Err> {
+        match tag {
+            "latest" => Ok(BlockTag::Latest),
+            "safe" => Ok(BlockTag::Safe),
+            "finalized" => Ok(BlockTag::Finalized),
+            _ => Err(format!("unknown block tag {tag}")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockRef {
+    pub number: u64,
+    pub hash: String,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Default)]
+struct Tagged {
+    latest: BlockRef,
+    safe: BlockRef,
+    finalized: BlockRef,
+}
+
+/// The block behind each [`BlockTag`]. Clones share the same blocks, so the chain can update
+/// them while the RPC reads them.
+#[derive(Clone, Debug, Default)]
+pub struct BlockTags {
+    tagged: Arc<RwLock<Tagged>>,
+}
+
+impl BlockTags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, tag: BlockTag) -> BlockRef {
+        let tagged = self
+            .tagged
+            .read()
+            .expect("block tags lock is never poisoned");
+        match tag {
+            BlockTag::Latest => tagged.latest.clone(),
+            BlockTag::Safe => tagged.safe.clone(),
+            BlockTag::Finalized => tagged.finalized.clone(),
+        }
+    }
+
+    pub fn set(&self, latest: BlockRef, safe: BlockRef, finalized: BlockRef) {
+        let mut tagged = self
+            .tagged
+            .write()
+            .expect("block tags lock is never poisoned");
+        *tagged = Tagged {
+            latest,
+            safe,
+            finalized,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_tag_names() {
+        for tag in [BlockTag::Latest, BlockTag::Safe, BlockTag::Finalized] {
+            assert_eq!(tag.to_string().parse(), Ok(tag));
+            assert_eq!(serde_json::to_string(&tag).unwrap(), format!("\"{tag}\""));
+        }
+        assert!("pending".parse::<BlockTag>().is_err());
+    }
+
+    #[test]
+    fn clones_share_the_tagged_blocks() {
+        let tags = BlockTags::new();
+        let shared = tags.clone();
+        let block = |number: u64| BlockRef {
+            number,
+            hash: number.to_string(),
+            timestamp: 0,
+        };
+        tags.set(block(3), block(2), block(1));
+        assert_eq!(shared.get(BlockTag::Latest), block(3));
+        assert_eq!(shared.get(BlockTag::Safe), block(2));
+        assert_eq!(shared.get(BlockTag::Finalized), block(1));
+    }
+}
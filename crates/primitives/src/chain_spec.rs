@@ -0,0 +1,406 @@
+//! Parameters of a chain, shared by all of its nodes as a JSON file.
+//!
+//! The genesis block commits to the hash of the whole spec, so nodes that disagree on any of it
+//! end up with different genesis hashes and refuse to peer.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs, io,
+    path::Path,
+};
+
+use rlp::{Encodable, RlpStream};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    amount::{FEL, U256},
+    signature::SignatureScheme,
+    transaction::keccak256,
+};
+
+/// Hashes have 256 bits, requiring more leading zeros can never be met.
+pub const MAX_DIFFICULTY: u32 = 256;
+
+/// Longest `extraData` of the genesis block, in bytes.
+pub const MAX_EXTRA_DATA: usize = 32;
+
+/// `maxFutureDrift` of specs that don't set it, in seconds.
+pub const DEFAULT_MAX_FUTURE_DRIFT: u64 = 15;
+
+/// `finality.confirmations` of specs that don't set it.
+pub const DEFAULT_CONFIRMATIONS: u64 = 12;
+
+/// `finality.checkpointInterval` of specs that don't set it.
+pub const DEFAULT_CHECKPOINT_INTERVAL: u64 = 16;
+
+#[derive(Debug)]
+pub enum ChainSpecError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ChainSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainSpecError::Io(err) => write!(f, "can't read chain spec: {err}"),
+            ChainSpecError::Json(err) => write!(f, "malformed chain spec: {err}"),
+            ChainSpecError::Invalid(msg) => write!(f, "invalid chain spec: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for ChainSpecError {}
+
+impl From<io::Error> for ChainSpecError {
+    fn from(err: io::Error) -> Self {
+        ChainSpecError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ChainSpecError {
+    fn from(err: serde_json::Error) -> Self {
+        ChainSpecError::Json(err)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ChainSpec {
+    pub chain_id: u64,
+    pub genesis: Genesis,
+    #[serde(default)]
+    pub engine: Engine,
+    /// Leading zero bits required of block hashes, under proof of work only.
+    pub difficulty: u32,
+    /// Targeted seconds between blocks.
+    pub block_time: u64,
+    /// Seconds a block's timestamp may be ahead of the clock of the node importing it.
+    #[serde(default = "default_max_future_drift")]
+    pub max_future_drift: u64,
+    #[serde(default)]
+    pub finality: Finality,
+    #[serde(default)]
+    pub reward: RewardSchedule,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Genesis {
+    /// Unix timestamp of the genesis block, in seconds.
+    pub timestamp: u64,
+    #[serde(default)]
+    pub extra_data: String,
+    /// Balances of the accounts that exist from the start, by address.
+    #[serde(default)]
+    pub alloc: BTreeMap<String, U256>,
+}
+
+/// How blocks are sealed and who may produce them.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub enum Engine {
+    /// Anyone may mine a block, its hash needs the difficulty of the spec.
+    #[default]
+    ProofOfWork,
+    /// Only `validators` sign blocks, each in turn: the block at height `n` is signed by
+    /// `validators[n % validators.len()]`.
+    ProofOfAuthority { validators: Vec<String> },
+}
+
+/// When blocks become final, the chain never reorganizes past the latest final block.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Finality {
+    /// Under proof of work, blocks this many blocks below the latest one are final.
+    #[serde(default = "default_confirmations")]
+    pub confirmations: u64,
+    /// Under proof of authority, validators sign a checkpoint for each block at a multiple of
+    /// this height, final once more than two thirds of them did.
+    #[serde(default = "default_checkpoint_interval")]
+    pub checkpoint_interval: u64,
+}
+
+impl Default for Finality {
+    fn default() -> Self {
+        Self {
+            confirmations: DEFAULT_CONFIRMATIONS,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+        }
+    }
+}
+
+impl Finality {
+    /// Checkpoint signatures needed out of `validators` for a block to be final.
+    pub fn quorum(validators: usize) -> usize {
+        validators * 2 / 3 + 1
+    }
+}
+
+/// What miners are paid for each block, on top of the fees.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct RewardSchedule {
+    /// Reward of the first blocks, before any halving.
+    #[serde(default)]
+    pub initial: U256,
+    /// Blocks between two halvings of the reward, `None` for a reward that never changes.
+    #[serde(default)]
+    pub halving_interval: Option<u64>,
+}
+
+impl RewardSchedule {
+    /// Reward of the block at `height`. The genesis block isn't mined and pays nothing.
+    pub fn at(&self, height: u64) -> U256 {
+        if height == 0 {
+            return U256::zero();
+        }
+        let halvings = match self.halving_interval {
+            Some(interval) => (height - 1) / interval,
+            None => 0,
+        };
+        // shifting by 256 bits or more would overflow, the reward is long gone by then anyway
+        if halvings >= 256 {
+            return U256::zero();
+        }
+        self.initial >> halvings as usize
+    }
+}
+
+impl ChainSpec {
+    /// Reads and validates the spec at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ChainSpecError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, ChainSpecError> {
+        let spec: Self = serde_json::from_str(json)?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    /// The spec nodes use when they're given none, for local networks.
+    pub fn local() -> Self {
+        Self {
+            chain_id: 1337,
+            genesis: Genesis {
+                timestamp: 1_672_531_200,
+                extra_data: "felipeum local".to_string(),
+                alloc: BTreeMap::new(),
+            },
+            engine: Engine::ProofOfWork,
+            difficulty: 16,
+            block_time: 10,
+            max_future_drift: DEFAULT_MAX_FUTURE_DRIFT,
+            finality: Finality::default(),
+            reward: RewardSchedule::default(),
+        }
+    }
+
+    /// The spec of a development node: blocks need no work and `account` starts with a million
+    /// FEL.
+    pub fn dev(account: &str) -> Self {
+        let mut spec = Self::local();
+        spec.genesis.extra_data = "felipeum dev".to_string();
+        spec.genesis
+            .alloc
+            .insert(account.to_string(), FEL * U256::from(1_000_000));
+        spec.difficulty = 0;
+        spec.block_time = 1;
+        spec
+    }
+
+    pub fn validate(&self) -> Result<(), ChainSpecError> {
+        let invalid = |msg: String| Err(ChainSpecError::Invalid(msg));
+        if self.difficulty > MAX_DIFFICULTY {
+            return invalid(format!(
+                "difficulty {} is above {MAX_DIFFICULTY}",
+                self.difficulty
+            ));
+        }
+        if self.block_time == 0 {
+            return invalid("block time must be at least 1 second".to_string());
+        }
+        if i64::try_from(self.max_future_drift).is_err() {
+            return invalid(format!(
+                "max future drift {} is out of range",
+                self.max_future_drift
+            ));
+        }
+        if i64::try_from(self.genesis.timestamp).is_err() {
+            return invalid(format!(
+                "genesis timestamp {} is out of range",
+                self.genesis.timestamp
+            ));
+        }
+        if self.finality.checkpoint_interval == 0 {
+            return invalid("checkpoint interval must be at least 1 block".to_string());
+        }
+        if self.genesis.extra_data.len() > MAX_EXTRA_DATA {
+            return invalid(format!("extra data is longer than {MAX_EXTRA_DATA} bytes"));
+        }
+        if self.reward.halving_interval == Some(0) {
+            return invalid("halving interval must be at least 1 block".to_string());
+        }
+        if let Engine::ProofOfAuthority { validators } = &self.engine {
+            if validators.is_empty() {
+                return invalid("proof of authority needs at least 1 validator".to_string());
+            }
+            if let Some(address) = validators
+                .iter()
+                .find(|address| !SignatureScheme::is_valid_address(address))
+            {
+                return invalid(format!("invalid validator address {address}"));
+            }
+            // seals and checkpoint votes are signed with ed25519 keys only
+            if let Some(address) = validators.iter().find(|address| {
+                SignatureScheme::of_address(address) != Some(SignatureScheme::Ed25519)
+            }) {
+                return invalid(format!("validator {address} isn't an ed25519 address"));
+            }
+            if validators.iter().collect::<BTreeSet<_>>().len() != validators.len() {
+                return invalid("validators are listed more than once".to_string());
+            }
+        }
+        if let Some(address) = self
+            .genesis
+            .alloc
+            .keys()
+            .find(|address| !SignatureScheme::is_valid_address(address))
+        {
+            return invalid(format!("invalid address {address} in alloc"));
+        }
+        let total = self
+            .genesis
+            .alloc
+            .values()
+            .try_fold(U256::zero(), |total, balance| total.checked_add(*balance));
+        if total.is_none() {
+            return invalid("allocated balances overflow".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Hash of the canonical encoding of the spec, committed to by the genesis block.
+    pub fn hash(&self) -> [u8; 32] {
+        keccak256(rlp::encode(self))
+    }
+}
+
+fn default_max_future_drift() -> u64 {
+    DEFAULT_MAX_FUTURE_DRIFT
+}
+
+fn default_confirmations() -> u64 {
+    DEFAULT_CONFIRMATIONS
+}
+
+fn default_checkpoint_interval() -> u64 {
+    DEFAULT_CHECKPOINT_INTERVAL
+}
+
+impl Encodable for ChainSpec {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(12);
+        s.append(&self.chain_id);
+        s.append(&self.genesis.timestamp);
+        s.append(&self.genesis.extra_data);
+        // sorted by address, like the map itself
+        s.begin_list(self.genesis.alloc.len());
+        for (address, balance) in &self.genesis.alloc {
+            s.begin_list(2);
+            s.append(address);
+            s.append(balance);
+        }
+        match &self.engine {
+            Engine::ProofOfWork => {
+                s.begin_list(1);
+                s.append(&0u8);
+            }
+            Engine::ProofOfAuthority { validators } => {
+                s.begin_list(2);
+                s.append(&1u8);
+                s.append_list::<String, _>(validators);
+            }
+        }
+        s.append(&self.difficulty);
+        s.append(&self.block_time);
+        s.append(&self.max_future_drift);
+        s.append(&self.finality.confirmations);
+        s.append(&self.finality.checkpoint_interval);
+        s.append(&self.reward.initial);
+        s.append(&self.reward.halving_interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain_spec_json() {
+        let json = r#"{
+            "chainId": 7,
+            "genesis": {
+                "timestamp": 1700000000,
+                "alloc": {
+                    "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a": "1000"
+                }
+            },
+            "difficulty": 12,
+            "blockTime": 5,
+            "reward": { "initial": "50", "halvingInterval": 100 }
+        }"#;
+        let spec = ChainSpec::from_json(json).unwrap();
+        assert_eq!(spec.genesis.alloc.values().next(), Some(&U256::from(1000)));
+        assert_eq!(spec.reward.halving_interval, Some(100));
+        assert_eq!(spec.max_future_drift, DEFAULT_MAX_FUTURE_DRIFT);
+        assert_eq!(spec.finality, Finality::default());
+        assert_eq!(Finality::quorum(1), 1);
+        assert_eq!(Finality::quorum(3), 3);
+        assert_eq!(Finality::quorum(4), 3);
+        assert!(spec.reward.at(0).is_zero());
+        assert_eq!(spec.reward.at(1), U256::from(50));
+        assert_eq!(spec.reward.at(100), U256::from(50));
+        assert_eq!(spec.reward.at(101), U256::from(25));
+        assert!(spec.reward.at(u64::MAX).is_zero());
+        assert_eq!(spec.hash(), ChainSpec::from_json(json).unwrap().hash());
+
+        let mut other = spec.clone();
+        other.chain_id = 8;
+        assert_ne!(other.hash(), spec.hash());
+
+        let mut invalid = spec.clone();
+        invalid.genesis.alloc.insert("bob".to_string(), U256::one());
+        assert!(matches!(
+            invalid.validate(),
+            Err(ChainSpecError::Invalid(_))
+        ));
+        let spec_hash = spec.hash();
+        invalid = spec;
+        invalid.block_time = 0;
+        assert!(invalid.validate().is_err());
+        invalid.block_time = 5;
+        invalid.finality.checkpoint_interval = 0;
+        assert!(invalid.validate().is_err());
+        invalid.finality.checkpoint_interval = DEFAULT_CHECKPOINT_INTERVAL;
+        invalid.engine = Engine::ProofOfAuthority { validators: vec![] };
+        assert!(invalid.validate().is_err());
+        invalid.engine = serde_json::from_str(
+            r#"{"proofOfAuthority": {"validators": [
+                "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"
+            ]}}"#,
+        )
+        .unwrap();
+        assert!(invalid.validate().is_ok());
+        assert_ne!(invalid.hash(), spec_hash);
+        invalid.engine = Engine::ProofOfAuthority {
+            validators: vec!["0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed".to_string()],
+        };
+        assert!(invalid.validate().is_err());
+        assert!(matches!(
+            ChainSpec::from_json(r#"{"chainId": 7, "unknown": 1}"#),
+            Err(ChainSpecError::Json(_))
+        ));
+    }
+}
@@ -0,0 +1,218 @@
+//! Canonical binary encoding used on the wire and in storage.
+//!
+//! Every type that leaves the node is RLP encoded through [`encode`] and read back through
+//! [`decode`], which enforces a per type size limit and never panics on malformed input.
+use std::fmt;
+
+use rlp::{Decodable, DecoderError, Encodable, Rlp};
+
+use crate::transaction::{Transaction, TransactionSigned};
+
+/// Largest accepted encoding of a single transaction.
+pub const MAX_TRANSACTION_SIZE: usize = 128 * 1024;
+
+/// Largest accepted encoding of a single block.
+pub const MAX_BLOCK_SIZE: usize = 4 * 1024 * 1024;
+
+/// A type with a canonical binary encoding.
+pub trait Codec: Encodable + Decodable {
+    /// Largest encoding [`decode`] accepts for this type.
+    const MAX_SIZE: usize;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodecError {
+    /// The input is bigger than the limit of the type being decoded.
+    TooLarge { size: usize, limit: usize },
+    /// The input is not a valid encoding of the type being decoded.
+    Malformed(DecoderError),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::TooLarge { size, limit } => {
+                write!(f, "encoding of {size} bytes exceeds limit of {limit} bytes")
+            }
+            CodecError::Malformed(err) => write!(f, "malformed encoding: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+impl From<DecoderError> for CodecError {
+    fn from(err: DecoderError) -> Self {
+        CodecError::Malformed(err)
+    }
+}
+
+pub fn encode<T: Codec>(value: &T) -> Vec<u8> {
+    rlp::encode(value).to_vec()
+}
+
+pub fn decode<T: Codec>(bytes: &[u8]) -> Result<T, CodecError> {
+    if bytes.len() > T::MAX_SIZE {
+        return Err(CodecError::TooLarge {
+            size: bytes.len(),
+            limit: T::MAX_SIZE,
+        });
+    }
+
+    let rlp = Rlp::new(bytes);
+    if rlp.payload_info()?.total() != bytes.len() {
+        return Err(DecoderError::RlpIsTooBig.into());
+    }
+
+    Ok(rlp.as_val()?)
+}
+
+/// Splits an RLP list into its items.
+///
+/// Unlike [`Rlp::iter`], which silently stops at the first malformed item, this fails if any
+/// byte of the list payload can't be attributed to an item.
+pub fn list_items<'a>(rlp: &Rlp<'a>) -> Result<Vec<Rlp<'a>>, DecoderError> {
+    if !rlp.is_list() {
+        return Err(DecoderError::RlpExpectedToBeList);
+    }
+
+    let mut rest = rlp.data()?;
+    let mut items = Vec::new();
+    while !rest.is_empty() {
+        let total = Rlp::new(rest).payload_info()?.total();
+        let (item, tail) = rest.split_at(total);
+        items.push(Rlp::new(item));
+        rest = tail;
+    }
+
+    Ok(items)
+}
+
+/// Same as [`list_items`] but also checks the list has exactly `count` items.
+pub fn expect_items<'a>(rlp: &Rlp<'a>, count: usize) -> Result<Vec<Rlp<'a>>, DecoderError> {
+    let items = list_items(rlp)?;
+    if items.len() != count {
+        return Err(DecoderError::RlpIncorrectListLen);
+    }
+
+    Ok(items)
+}
+
+/// Decodes every item of an RLP list as `T`.
+pub fn decode_list<T: Decodable>(rlp: &Rlp) -> Result<Vec<T>, DecoderError> {
+    list_items(rlp)?.iter().map(|item| item.as_val()).collect()
+}
+
+/// Decodes an optional value, encoded by rlp as a list of zero or one items.
+pub fn decode_option<T: Decodable>(rlp: &Rlp) -> Result<Option<T>, DecoderError> {
+    match list_items(rlp)?.as_slice() {
+        [] => Ok(None),
+        [item] => Ok(Some(item.as_val()?)),
+        _ => Err(DecoderError::RlpIncorrectListLen),
+    }
+}
+
+impl Codec for Transaction {
+    const MAX_SIZE: usize = MAX_TRANSACTION_SIZE;
+}
+
+impl Codec for TransactionSigned {
+    const MAX_SIZE: usize = MAX_TRANSACTION_SIZE;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        amount::U256,
+        signature::{Signature, SignatureScheme, TransactionSignature},
+        transaction::{Timelock, TransactionKind, Transfer},
+    };
+
+    fn transaction_signed() -> TransactionSigned {
+        TransactionSigned {
+            hash: "0xabcdef".to_string(),
+            signature: TransactionSignature::Single(Signature::new(
+                SignatureScheme::Ed25519,
+                &[7u8; 64],
+            )),
+            transaction: Transaction {
+                fee: U256::from(2),
+                valid_after: Some(Timelock::Height(5)),
+                ..Transaction::transfer("alice", "bob", 10, 1)
+            },
+        }
+    }
+
+    #[test]
+    fn roundtrip_transaction_signed() {
+        let tx = transaction_signed();
+        let decoded: TransactionSigned = decode(&encode(&tx)).unwrap();
+
+        assert_eq!(encode(&decoded), encode(&tx));
+        assert_eq!(decoded.transaction.nonce, 1);
+        assert_eq!(decoded.transaction.from, "alice");
+        assert_eq!(decoded.transaction.valid_after, Some(Timelock::Height(5)));
+    }
+
+    #[test]
+    fn malformed_input_is_an_error() {
+        let encoded = encode(&transaction_signed());
+
+        for len in 0..encoded.len() {
+            assert!(decode::<TransactionSigned>(&encoded[..len]).is_err());
+        }
+        for i in 0..encoded.len() {
+            for bit in 0..8 {
+                let mut corrupted = encoded.clone();
+                corrupted[i] ^= 1 << bit;
+                let _ = decode::<TransactionSigned>(&corrupted);
+            }
+        }
+
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert_eq!(
+            decode::<TransactionSigned>(&trailing).unwrap_err(),
+            CodecError::Malformed(DecoderError::RlpIsTooBig)
+        );
+    }
+
+    #[test]
+    fn addresses_are_lowercase() {
+        let mut tx = transaction_signed();
+        tx.transaction.from = "Alice".to_string();
+        assert_eq!(
+            decode::<TransactionSigned>(&encode(&tx)).unwrap_err(),
+            CodecError::Malformed(DecoderError::Custom("addresses must be lowercase"))
+        );
+
+        let mut tx = transaction_signed();
+        tx.transaction.kind = TransactionKind::Batch(vec![
+            Transfer {
+                to: "bob".to_string(),
+                value: U256::one(),
+            },
+            Transfer {
+                to: "0xABC".to_string(),
+                value: U256::one(),
+            },
+        ]);
+        assert!(decode::<TransactionSigned>(&encode(&tx)).is_err());
+
+        // nor can signatures be of a length no scheme has
+        let mut tx = transaction_signed();
+        tx.signature = TransactionSignature::Single(Signature::new(SignatureScheme::Ed25519, &[7]));
+        assert!(decode::<TransactionSigned>(&encode(&tx)).is_err());
+    }
+
+    #[test]
+    fn oversized_input_is_rejected() {
+        let bytes = vec![0u8; MAX_TRANSACTION_SIZE + 1];
+
+        assert!(matches!(
+            decode::<TransactionSigned>(&bytes),
+            Err(CodecError::TooLarge { .. })
+        ));
+    }
+}
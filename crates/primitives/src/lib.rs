@@ -1,6 +1,12 @@
 // use bits::B160;
 
 // pub mod bits;
+pub mod amount;
+pub mod block_tag;
+pub mod chain_spec;
+pub mod codec;
+pub mod multisig;
+pub mod receipt;
 pub mod signature;
 pub mod transaction;
 
@@ -0,0 +1,117 @@
+//! M-of-N accounts, controlled by a set of public keys and a signing threshold.
+use std::{collections::HashSet, fmt};
+
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    codec::{decode_list, expect_items},
+    signature::SignatureScheme,
+    transaction::keccak256,
+};
+
+/// Most keys a single multisig account can be controlled by.
+pub const MAX_MULTISIG_KEYS: usize = 16;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultisigPolicy {
+    /// How many distinct keys of `public_keys` have to sign a transaction.
+    pub threshold: u8,
+    /// Addresses of the keys allowed to sign for the account, of any [`SignatureScheme`].
+    pub public_keys: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyError {
+    ZeroThreshold,
+    ThresholdTooHigh { threshold: u8, keys: usize },
+    TooManyKeys(usize),
+    DuplicateKey(String),
+    InvalidKey(String),
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyError::ZeroThreshold => write!(f, "threshold must be at least 1"),
+            PolicyError::ThresholdTooHigh { threshold, keys } => {
+                write!(f, "threshold {threshold} is higher than the {keys} keys")
+            }
+            PolicyError::TooManyKeys(keys) => {
+                write!(f, "{keys} keys exceed the limit of {MAX_MULTISIG_KEYS}")
+            }
+            PolicyError::DuplicateKey(key) => write!(f, "duplicate key {key}"),
+            PolicyError::InvalidKey(key) => write!(f, "invalid public key {key}"),
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+impl MultisigPolicy {
+    pub fn new(threshold: u8, public_keys: Vec<String>) -> Self {
+        Self {
+            threshold,
+            public_keys,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), PolicyError> {
+        if self.threshold == 0 {
+            return Err(PolicyError::ZeroThreshold);
+        }
+        if self.public_keys.len() > MAX_MULTISIG_KEYS {
+            return Err(PolicyError::TooManyKeys(self.public_keys.len()));
+        }
+        if usize::from(self.threshold) > self.public_keys.len() {
+            return Err(PolicyError::ThresholdTooHigh {
+                threshold: self.threshold,
+                keys: self.public_keys.len(),
+            });
+        }
+
+        let mut seen = HashSet::new();
+        for key in &self.public_keys {
+            if !SignatureScheme::is_valid_address(key) {
+                return Err(PolicyError::InvalidKey(key.clone()));
+            }
+            if !seen.insert(key) {
+                return Err(PolicyError::DuplicateKey(key.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn contains(&self, public_key: &str) -> bool {
+        self.public_keys.iter().any(|key| key == public_key)
+    }
+}
+
+/// Address of the multisig account created by `creator` with the transaction at `nonce`.
+///
+/// Derived like a contract address, so it stays the same when the policy is updated later on.
+pub fn multisig_address(creator: &str, nonce: u64) -> String {
+    let mut s = RlpStream::new_list(2);
+    s.append(&creator);
+    s.append(&nonce);
+    hex::encode(keccak256(s.out()))
+}
+
+impl Encodable for MultisigPolicy {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(2);
+        s.append(&self.threshold);
+        s.append_list::<String, _>(&self.public_keys);
+    }
+}
+
+impl Decodable for MultisigPolicy {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let items = expect_items(rlp, 2)?;
+        Ok(Self {
+            threshold: items[0].as_val()?,
+            public_keys: decode_list(&items[1])?,
+        })
+    }
+}
@@ -0,0 +1,157 @@
+//! What happened to each transaction included in a block.
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use rlp::RlpStream;
+use serde::{Deserialize, Serialize};
+
+use crate::{amount::U256, transaction::keccak256, TxHash};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReceiptStatus {
+    Success,
+    /// The transaction was included and paid its fee, but changed nothing else.
+    Failed,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Receipt {
+    pub transaction_hash: TxHash,
+    /// Filled in once the block is sealed, the executor doesn't know its hash.
+    pub block_hash: String,
+    pub block_number: u64,
+    /// Position of the transaction in the block.
+    pub index: u64,
+    pub status: ReceiptStatus,
+    pub fee_paid: U256,
+    /// Code of the reason the transaction failed, `None` when it succeeded. Codes are stable and
+    /// never 0, the receipts root commits to them.
+    pub error_code: Option<u16>,
+    /// Why the transaction failed, for people. Wording may change between releases, so it's
+    /// left out of the receipts root.
+    pub error: Option<String>,
+}
+
+/// Commits to the outcome of the transactions of a block, so the block hash covers them. The
+/// block hash and number aren't known yet when it's computed, so they're left out, as is the
+/// error message.
+pub fn receipts_root(receipts: &[Receipt]) -> String {
+    let mut s = RlpStream::new_list(receipts.len());
+    for receipt in receipts {
+        s.begin_list(5);
+        s.append(&receipt.transaction_hash);
+        s.append(&receipt.index);
+        s.append(&(receipt.status == ReceiptStatus::Success));
+        s.append(&receipt.fee_paid);
+        s.append(&receipt.error_code.unwrap_or_default());
+    }
+    hex::encode(keccak256(s.out()))
+}
+
+/// Receipts of the transactions of the chain by transaction hash. Clones share the same
+/// receipts, so the chain can store them while the RPC reads them.
+#[derive(Clone, Debug, Default)]
+pub struct ReceiptStore {
+    receipts: Arc<RwLock<HashMap<TxHash, Receipt>>>,
+}
+
+impl ReceiptStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, hash: &str) -> Option<Receipt> {
+        self.receipts
+            .read()
+            .expect("receipts lock is never poisoned")
+            .get(hash)
+            .cloned()
+    }
+
+    pub fn insert(&self, receipts: impl IntoIterator<Item = Receipt>) {
+        let mut stored = self
+            .receipts
+            .write()
+            .expect("receipts lock is never poisoned");
+        for receipt in receipts {
+            stored.insert(receipt.transaction_hash.clone(), receipt);
+        }
+    }
+
+    /// Replaces all receipts, once the chain was replaced by another one.
+    pub fn reset(&self, receipts: impl IntoIterator<Item = Receipt>) {
+        let mut stored = self
+            .receipts
+            .write()
+            .expect("receipts lock is never poisoned");
+        *stored = receipts
+            .into_iter()
+            .map(|receipt| (receipt.transaction_hash.clone(), receipt))
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt(hash: &str, index: u64) -> Receipt {
+        Receipt {
+            transaction_hash: hash.to_string(),
+            block_hash: String::new(),
+            block_number: 0,
+            index,
+            status: ReceiptStatus::Success,
+            fee_paid: U256::one(),
+            error_code: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn receipts_root_commits_to_the_outcome() {
+        let receipts = [receipt("a", 0), receipt("b", 1)];
+        let root = receipts_root(&receipts);
+        assert_ne!(root, receipts_root(&[receipt("b", 0), receipt("a", 1)]));
+        assert_ne!(root, receipts_root(&receipts[..1]));
+
+        let mut failed = receipts.clone();
+        failed[1].status = ReceiptStatus::Failed;
+        failed[1].error_code = Some(3);
+        failed[1].error = Some("insufficient balance".to_string());
+        let failed_root = receipts_root(&failed);
+        assert_ne!(failed_root, root);
+        failed[1].error_code = Some(4);
+        assert_ne!(receipts_root(&failed), failed_root);
+
+        // only the code counts, the message and where the block ended up don't
+        failed[1].error_code = Some(3);
+        failed[1].error = Some("reworded".to_string());
+        failed[1].block_hash = "block".to_string();
+        failed[1].block_number = 7;
+        assert_eq!(receipts_root(&failed), failed_root);
+    }
+
+    #[test]
+    fn receipt_store() {
+        let store = ReceiptStore::new();
+        let shared = store.clone();
+        store.insert([receipt("a", 0), receipt("b", 1)]);
+        assert_eq!(shared.get("a"), Some(receipt("a", 0)));
+
+        let mut moved = receipt("a", 3);
+        moved.block_number = 2;
+        store.insert([moved.clone()]);
+        assert_eq!(shared.get("a"), Some(moved));
+        assert_eq!(shared.get("b"), Some(receipt("b", 1)));
+
+        store.reset([receipt("c", 0)]);
+        assert_eq!(shared.get("a"), None);
+        assert_eq!(shared.get("b"), None);
+        assert_eq!(shared.get("c"), Some(receipt("c", 0)));
+    }
+}
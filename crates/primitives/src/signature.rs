@@ -1,12 +1,50 @@
+use std::{fmt, str::FromStr};
+
+use curve25519_dalek::edwards::CompressedEdwardsY;
 use ed25519_dalek::{PublicKey, SignatureError};
+use k256::ecdsa::{RecoveryId, VerifyingKey};
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    codec::{decode_list, expect_items},
+    transaction::{keccak256, TransactionSigned},
+};
+
+/// A signature along with the scheme of the key that made it.
+///
+/// Only the bytes go over the wire, each scheme has its own signature length.
 #[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct Signature(Vec<u8>);
+#[serde(try_from = "Vec<u8>", into = "Vec<u8>")]
+pub struct Signature {
+    scheme: SignatureScheme,
+    bytes: Vec<u8>,
+}
 
 impl Signature {
-    pub fn new(signature_slice: &[u8]) -> Self {
-        Self(signature_slice.to_owned())
+    pub fn new(scheme: SignatureScheme, signature_slice: &[u8]) -> Self {
+        Self {
+            scheme,
+            bytes: signature_slice.to_owned(),
+        }
+    }
+
+    /// Parses a signature received as bytes, `None` if its length is of neither scheme.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let scheme = match bytes.len() {
+            SignatureScheme::ED25519_SIGNATURE_LENGTH => SignatureScheme::Ed25519,
+            SignatureScheme::SECP256K1_SIGNATURE_LENGTH => SignatureScheme::Secp256k1,
+            _ => return None,
+        };
+        Some(Self::new(scheme, bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn scheme(&self) -> SignatureScheme {
+        self.scheme
     }
 
     fn verify_verbose(
@@ -15,11 +53,623 @@ impl Signature {
         message_bytes: &[u8],
     ) -> Result<(), SignatureError> {
         let publickey = PublicKey::from_bytes(pubkey_bytes)?;
-        let signature = self.0.as_slice().try_into()?;
+        let signature = self.bytes.as_slice().try_into()?;
         publickey.verify_strict(message_bytes, &signature)
     }
 
+    /// Checks an ed25519 signature against `pubkey_bytes`, false for secp256k1 signatures.
     pub fn verify(&self, pubkey_bytes: &[u8], message_bytes: &[u8]) -> bool {
-        self.verify_verbose(pubkey_bytes, message_bytes).is_ok()
+        self.scheme == SignatureScheme::Ed25519
+            && self.verify_verbose(pubkey_bytes, message_bytes).is_ok()
+    }
+
+    /// Checks the signature was made by the key of `address`, which has to be of the same
+    /// scheme.
+    ///
+    /// ed25519 addresses are the public key itself, secp256k1 ones are compared with the address
+    /// recovered from the signature. Addresses are matched exactly, in the lowercase form
+    /// accounts are keyed by, see [`parse_address`] for user input.
+    pub fn verify_address(&self, address: &str, message_bytes: &[u8]) -> bool {
+        if SignatureScheme::of_address(address) != Some(self.scheme) {
+            return false;
+        }
+        match self.scheme {
+            SignatureScheme::Ed25519 => hex::decode(address)
+                .map(|public_key| self.verify(&public_key, message_bytes))
+                .unwrap_or_default(),
+            SignatureScheme::Secp256k1 => self
+                .recover_address(message_bytes)
+                .is_some_and(|recovered| recovered == address),
+        }
+    }
+
+    /// Recovers the address of the secp256k1 key that signed `hash`, `None` for ed25519
+    /// signatures.
+    pub fn recover_address(&self, hash: &[u8]) -> Option<String> {
+        if self.scheme != SignatureScheme::Secp256k1
+            || self.bytes.len() != SignatureScheme::SECP256K1_SIGNATURE_LENGTH
+        {
+            return None;
+        }
+        let signature = k256::ecdsa::Signature::from_slice(&self.bytes[..64]).ok()?;
+        // a high s is the malleable twin of a valid signature, only the low one is accepted
+        if signature.normalize_s().is_some() {
+            return None;
+        }
+        let recovery_id = RecoveryId::from_byte(self.bytes[64])?;
+        let public_key = VerifyingKey::recover_from_prehash(hash, &signature, recovery_id).ok()?;
+        Some(secp256k1_address(&public_key))
+    }
+}
+
+/// Kinds of keys an account can be controlled by.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureScheme {
+    /// Addresses are the hex encoded 32 byte public key, signatures are 64 bytes.
+    #[default]
+    Ed25519,
+    /// ECDSA with public key recovery, as used by Ethereum. Addresses are `0x` followed by the
+    /// last 20 bytes of the keccak256 of the uncompressed public key, signatures are `r || s || v`
+    /// in 65 bytes.
+    Secp256k1,
+}
+
+impl SignatureScheme {
+    pub const ED25519_SIGNATURE_LENGTH: usize = 64;
+    pub const SECP256K1_SIGNATURE_LENGTH: usize = 65;
+
+    /// The scheme of the key behind `address`: `0x` and 40 lowercase hex digits for secp256k1,
+    /// 64 lowercase hex digits for ed25519. `None` for anything else, including either form in
+    /// another case.
+    pub fn of_address(address: &str) -> Option<Self> {
+        let is_lower_hex = |hex: &str, len: usize| {
+            hex.len() == len && hex.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        };
+        match address.strip_prefix("0x") {
+            Some(hex) if is_lower_hex(hex, 40) => Some(SignatureScheme::Secp256k1),
+            None if is_lower_hex(address, 64) => Some(SignatureScheme::Ed25519),
+            _ => None,
+        }
+    }
+
+    /// Checks `address` is the address of a key of either scheme, and for ed25519 that it is a
+    /// point on the curve.
+    pub fn is_valid_address(address: &str) -> bool {
+        match Self::of_address(address) {
+            Some(SignatureScheme::Ed25519) => hex::decode(address)
+                .map(|bytes| PublicKey::from_bytes(&bytes).is_ok())
+                .unwrap_or_default(),
+            Some(SignatureScheme::Secp256k1) => true,
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for SignatureScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureScheme::Ed25519 => write!(f, "ed25519"),
+            SignatureScheme::Secp256k1 => write!(f, "secp256k1"),
+        }
+    }
+}
+
+impl FromStr for SignatureScheme {
+    type Err = String;
+
+    fn from_str(scheme: &str) -> Result<Self, Self::Err> {
+        match scheme {
+            "ed25519" => Ok(SignatureScheme::Ed25519),
+            "secp256k1" => Ok(SignatureScheme::Secp256k1),
+            _ => Err(format!("unknown signature scheme {scheme:?}")),
+        }
+    }
+}
+
+/// The address of a secp256k1 public key, lowercase.
+pub fn secp256k1_address(public_key: &VerifyingKey) -> String {
+    let point = public_key.to_encoded_point(false);
+    let hash = keccak256(&point.as_bytes()[1..]);
+    format!("0x{}", hex::encode(&hash[12..]))
+}
+
+/// The address of `public_key`, told apart by its length: 32 bytes for ed25519, or a SEC1
+/// encoded secp256k1 key. `None` if it's neither.
+pub fn public_key_address(public_key: &[u8]) -> Option<String> {
+    if public_key.len() == 32 {
+        return PublicKey::from_bytes(public_key)
+            .ok()
+            .map(|_| hex::encode(public_key));
+    }
+    VerifyingKey::from_sec1_bytes(public_key)
+        .ok()
+        .map(|public_key| secp256k1_address(&public_key))
+}
+
+/// The EIP-55 form of a secp256k1 `address`: letters are uppercase where the matching nibble of
+/// the keccak256 of the lowercase address is 8 or more.
+pub fn checksum_address(address: &str) -> String {
+    let hex = address.trim_start_matches("0x").to_ascii_lowercase();
+    let hash = keccak256(&hex);
+    let checksummed: String = hex
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = (hash[i / 2] >> (4 * (1 - i % 2))) & 0xf;
+            if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect();
+    format!("0x{checksummed}")
+}
+
+/// Parses an address given by a user into the lowercase form accounts are keyed by.
+///
+/// Any case is accepted, as long as a mixed case secp256k1 address carries a valid EIP-55
+/// checksum. `None` if it isn't a valid address.
+pub fn parse_address(address: &str) -> Option<String> {
+    if address.starts_with("0x") && !has_valid_checksum(address) {
+        return None;
+    }
+    let address = address.to_ascii_lowercase();
+    SignatureScheme::is_valid_address(&address).then_some(address)
+}
+
+/// Single case addresses carry no checksum, mixed case ones must be in their EIP-55 form.
+fn has_valid_checksum(address: &str) -> bool {
+    let hex = address.trim_start_matches("0x");
+    !hex.bytes().any(|b| b.is_ascii_lowercase())
+        || !hex.bytes().any(|b| b.is_ascii_uppercase())
+        || address == checksum_address(address)
+}
+
+/// A signature to check as part of a batch, see [`verify_batch`].
+#[derive(Clone, Copy, Debug)]
+pub struct SignatureItem<'a> {
+    pub public_key: &'a [u8],
+    pub message: &'a [u8],
+    pub signature: &'a Signature,
+}
+
+impl SignatureItem<'_> {
+    fn verify(&self) -> bool {
+        self.signature.verify(self.public_key, self.message)
+    }
+
+    /// Parses the item, rejecting the small order points [`Signature::verify`] rejects as well,
+    /// and points with a torsion component.
+    ///
+    /// The batch equation doesn't multiply by the cofactor, so a torsion component of R or A can
+    /// cancel out for some batch coefficients while [`Signature::verify`] always rejects it.
+    /// Keeping them out of batches means a batch only passes if every item passes on its own.
+    fn parse(&self) -> Option<(PublicKey, ed25519_dalek::Signature)> {
+        let public_key = PublicKey::from_bytes(self.public_key).ok()?;
+        let signature = ed25519_dalek::Signature::try_from(self.signature.as_bytes()).ok()?;
+        let is_prime_order = |bytes: &[u8]| {
+            CompressedEdwardsY::from_slice(bytes)
+                .decompress()
+                .is_some_and(|point| !point.is_small_order() && point.is_torsion_free())
+        };
+        if !is_prime_order(public_key.as_bytes()) || !is_prime_order(&signature.to_bytes()[..32]) {
+            return None;
+        }
+        Some((public_key, signature))
+    }
+}
+
+/// Verifies all `items` at once, which is several times faster than one by one.
+///
+/// If the batch fails every item is checked on its own, and the error holds the indexes of the
+/// bad ones. The coefficients of the batch equation are derived from its inputs, so all nodes
+/// reach the same verdict on the same batch.
+pub fn verify_batch(items: &[SignatureItem]) -> Result<(), Vec<usize>> {
+    let parsed: Option<Vec<_>> = items.iter().map(SignatureItem::parse).collect();
+    if let Some(parsed) = parsed {
+        let messages: Vec<&[u8]> = items.iter().map(|item| item.message).collect();
+        let (public_keys, signatures): (Vec<_>, Vec<_>) = parsed.into_iter().unzip();
+        if ed25519_dalek::verify_batch(&messages, &signatures, &public_keys).is_ok() {
+            return Ok(());
+        }
+    }
+
+    let invalid: Vec<usize> = items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| !item.verify())
+        .map(|(index, _)| index)
+        .collect();
+    if invalid.is_empty() {
+        Ok(())
+    } else {
+        Err(invalid)
+    }
+}
+
+/// Verifies the signatures of all `transactions` in one batch.
+///
+/// Only checks the signatures are valid for the keys they name, whether those keys may spend
+/// from the sender is up to the executor. On failure returns the indexes of the transactions
+/// with a bad signature.
+///
+/// secp256k1 signatures can't be batched and are checked one by one.
+pub fn verify_transactions<'a>(
+    transactions: impl IntoIterator<Item = &'a TransactionSigned>,
+) -> Result<(), Vec<usize>> {
+    let transactions: Vec<&TransactionSigned> = transactions.into_iter().collect();
+    let hashes: Vec<[u8; 32]> = transactions
+        .iter()
+        .map(|tx| tx.transaction.signature_hash())
+        .collect();
+
+    let mut invalid = Vec::new();
+    let mut keys = Vec::new();
+    for (index, tx) in transactions.iter().enumerate() {
+        let signers: Vec<(&str, &Signature)> = match &tx.signature {
+            TransactionSignature::Single(signature) => vec![(&tx.transaction.from, signature)],
+            TransactionSignature::Multi(signatures) => signatures
+                .iter()
+                .map(|signer| (signer.public_key.as_str(), &signer.signature))
+                .collect(),
+        };
+        for (address, signature) in signers {
+            match SignatureScheme::of_address(address) {
+                Some(SignatureScheme::Ed25519)
+                    if signature.scheme() == SignatureScheme::Ed25519 =>
+                {
+                    keys.push((index, hex::decode(address).unwrap_or_default(), signature))
+                }
+                _ => {
+                    if !signature.verify_address(address, &hashes[index]) {
+                        invalid.push(index);
+                    }
+                }
+            }
+        }
+    }
+
+    let items: Vec<SignatureItem> = keys
+        .iter()
+        .map(|(index, public_key, signature)| SignatureItem {
+            public_key,
+            message: &hashes[*index],
+            signature,
+        })
+        .collect();
+    if let Err(bad) = verify_batch(&items) {
+        invalid.extend(bad.into_iter().map(|item| keys[item].0));
+    }
+
+    if invalid.is_empty() {
+        Ok(())
+    } else {
+        invalid.sort_unstable();
+        invalid.dedup();
+        Err(invalid)
+    }
+}
+
+/// Prepended, along with the message length, to off-chain messages before hashing them for
+/// signing. It differs from [`TRANSACTION_DOMAIN`] early on, so no signed message is ever a valid
+/// transaction signature and the other way around.
+pub const MESSAGE_PREFIX: &[u8] = b"\x19Felipeum Signed Message:\n";
+
+/// The hash signed for `message`, `keccak256(MESSAGE_PREFIX || len(message) || message)` with
+/// the length in decimal.
+pub fn message_hash(message: &[u8]) -> [u8; 32] {
+    let mut data = MESSAGE_PREFIX.to_vec();
+    data.extend_from_slice(message.len().to_string().as_bytes());
+    data.extend_from_slice(message);
+    keccak256(data)
+}
+
+/// Checks `signature` is the signature of `message` by the key of `address`, see
+/// [`message_hash`].
+pub fn verify_message(address: &str, message: &[u8], signature: &Signature) -> bool {
+    signature.verify_address(address, &message_hash(message))
+}
+
+impl Encodable for Signature {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        // appending the inner vec through the stream would count it as an extra list item
+        self.bytes.rlp_append(s);
+    }
+}
+
+impl Decodable for Signature {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        Self::from_bytes(&rlp.as_val::<Vec<u8>>()?)
+            .ok_or(DecoderError::Custom("invalid signature length"))
+    }
+}
+
+impl TryFrom<Vec<u8>> for Signature {
+    type Error = &'static str;
+
+    fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
+        Self::from_bytes(&bytes).ok_or("invalid signature length")
+    }
+}
+
+impl From<Signature> for Vec<u8> {
+    fn from(signature: Signature) -> Self {
+        signature.bytes
+    }
+}
+
+/// Signature made by one of the keys of a multisig account.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SignerSignature {
+    /// Address of the signing key, the public key itself for ed25519.
+    pub public_key: String,
+    pub signature: Signature,
+}
+
+/// Signatures authorizing a transaction.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum TransactionSignature {
+    /// Signature of the key the sender address belongs to.
+    Single(Signature),
+    /// Signatures of the keys of a multisig sender, checked against its policy.
+    Multi(Vec<SignerSignature>),
+}
+
+impl Encodable for SignerSignature {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(2);
+        s.append(&self.public_key);
+        s.append(&self.signature);
+    }
+}
+
+impl Decodable for SignerSignature {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let items = expect_items(rlp, 2)?;
+        Ok(Self {
+            public_key: items[0].as_val()?,
+            signature: items[1].as_val()?,
+        })
+    }
+}
+
+// a single signature is plain data and multiple signatures are a list, so single signed
+// transactions keep the exact same encoding they had before multisig existed
+impl Encodable for TransactionSignature {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        match self {
+            TransactionSignature::Single(signature) => signature.rlp_append(s),
+            TransactionSignature::Multi(signatures) => {
+                s.append_list::<SignerSignature, _>(signatures);
+            }
+        }
+    }
+}
+
+impl Decodable for TransactionSignature {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        if rlp.is_list() {
+            Ok(TransactionSignature::Multi(decode_list(rlp)?))
+        } else {
+            Ok(TransactionSignature::Single(rlp.as_val()?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use curve25519_dalek::traits::Identity;
+    use ed25519_dalek::{Keypair, SecretKey, Signer};
+
+    use super::*;
+    use crate::{
+        amount::U256,
+        transaction::{Transaction, TRANSACTION_DOMAIN},
+    };
+
+    fn keypair(seed: u8) -> Keypair {
+        let secret = SecretKey::from_bytes(&[seed; 32]).unwrap();
+        let public = PublicKey::from(&secret);
+        Keypair { secret, public }
+    }
+
+    fn transaction(from: &Keypair, nonce: u64) -> Transaction {
+        Transaction {
+            fee: U256::one(),
+            ..Transaction::transfer(&hex::encode(from.public.as_bytes()), "bob", 10, nonce)
+        }
+    }
+
+    fn sign(keypair: &Keypair, transaction: &Transaction) -> Signature {
+        Signature::new(
+            SignatureScheme::Ed25519,
+            &keypair.sign(&transaction.signature_hash()).to_bytes(),
+        )
+    }
+
+    fn signed(transaction: Transaction, signature: TransactionSignature) -> TransactionSigned {
+        TransactionSigned {
+            hash: hex::encode(transaction.signature_hash()),
+            signature,
+            transaction,
+        }
+    }
+
+    #[test]
+    fn batch_verification_pinpoints_bad_transactions() {
+        let mut transactions: Vec<TransactionSigned> = (0..4)
+            .map(|i| {
+                let keypair = keypair(i);
+                let tx = transaction(&keypair, i.into());
+                let signature = TransactionSignature::Single(sign(&keypair, &tx));
+                signed(tx, signature)
+            })
+            .collect();
+        let cosigners = [keypair(10), keypair(11)];
+        let tx = transaction(&keypair(12), 0);
+        let signatures = cosigners
+            .iter()
+            .map(|cosigner| SignerSignature {
+                public_key: hex::encode(cosigner.public.as_bytes()),
+                signature: sign(cosigner, &tx),
+            })
+            .collect();
+        transactions.push(signed(tx, TransactionSignature::Multi(signatures)));
+        assert_eq!(verify_transactions(&transactions), Ok(()));
+
+        // signed by the wrong key
+        let tx = transactions[1].transaction.clone();
+        transactions[1].signature = TransactionSignature::Single(sign(&keypair(9), &tx));
+        // one cosigner signed something else
+        let other = sign(&cosigners[1], &transactions[0].transaction);
+        if let TransactionSignature::Multi(signatures) = &mut transactions[4].signature {
+            signatures[1].signature = other;
+        }
+        assert_eq!(verify_transactions(&transactions), Err(vec![1, 4]));
+
+        // the identity point is a small order key, under which anyone can forge a signature
+        transactions[3].transaction.from = hex::encode(CompressedEdwardsY::identity().as_bytes());
+        assert_eq!(verify_transactions(&transactions), Err(vec![1, 3, 4]));
+    }
+
+    #[test]
+    fn torsioned_nonce_fails_batch_and_single() {
+        use curve25519_dalek::{
+            constants::{ED25519_BASEPOINT_POINT, EIGHT_TORSION},
+            edwards::EdwardsPoint,
+            scalar::Scalar,
+        };
+        use sha2::{Digest, Sha512};
+
+        let secret = Scalar::from_bytes_mod_order([7; 32]);
+        let public_key = (secret * ED25519_BASEPOINT_POINT).compress();
+        let nonce = Scalar::from_bytes_mod_order([9; 32]);
+        let message: &[u8] = b"torsion";
+        // signs by hand with `r` as the nonce point, which should be nonce * B
+        let sign = |r: EdwardsPoint| {
+            let r = r.compress();
+            let k = Scalar::from_hash(
+                Sha512::new()
+                    .chain(r.as_bytes())
+                    .chain(public_key.as_bytes())
+                    .chain(message),
+            );
+            let mut bytes = r.to_bytes().to_vec();
+            bytes.extend_from_slice((nonce + k * secret).as_bytes());
+            Signature::new(SignatureScheme::Ed25519, &bytes)
+        };
+        let item = |signature| SignatureItem {
+            public_key: public_key.as_bytes(),
+            message,
+            signature,
+        };
+
+        let valid = sign(nonce * ED25519_BASEPOINT_POINT);
+        assert!(valid.verify(public_key.as_bytes(), message));
+        assert_eq!(verify_batch(&[item(&valid), item(&valid)]), Ok(()));
+
+        // a torsion point added to R passes the cofactored equation but not the strict one
+        let torsioned = sign(nonce * ED25519_BASEPOINT_POINT + EIGHT_TORSION[1]);
+        assert!(!torsioned.verify(public_key.as_bytes(), message));
+        assert!(item(&torsioned).parse().is_none());
+        assert_eq!(
+            verify_batch(&[item(&valid), item(&torsioned)]),
+            Err(vec![1])
+        );
+    }
+
+    #[test]
+    fn secp256k1_addresses_are_matched_lowercase() {
+        // from the EIP-55 test vectors
+        assert_eq!(
+            checksum_address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"),
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"
+        );
+
+        let key = k256::ecdsa::SigningKey::from_slice(&[7; 32]).unwrap();
+        let address = secp256k1_address(key.verifying_key());
+        assert_eq!(
+            public_key_address(&key.verifying_key().to_sec1_bytes()),
+            Some(address.clone())
+        );
+        let hash = message_hash(b"hello");
+        let (signature, recovery_id) = key.sign_prehash_recoverable(&hash).unwrap();
+        let mut bytes = signature.to_vec();
+        bytes.push(recovery_id.to_byte());
+        let signature = Signature::from_bytes(&bytes).unwrap();
+        assert_eq!(signature.scheme(), SignatureScheme::Secp256k1);
+
+        // the same key must not control a second account spelled in another case
+        let uppercase = format!("0x{}", address[2..].to_ascii_uppercase());
+        let checksummed = checksum_address(&address);
+        assert_ne!(checksummed, address);
+        assert!(signature.verify_address(&address, &hash));
+        assert!(!signature.verify_address(&uppercase, &hash));
+        assert!(!signature.verify_address(&checksummed, &hash));
+
+        // users may type them in any case, they're parsed into the lowercase form
+        assert_eq!(parse_address(&uppercase), Some(address.clone()));
+        assert_eq!(parse_address(&checksummed), Some(address.clone()));
+        // flipping the case of a single letter breaks the checksum
+        let i = checksummed
+            .rfind(|c: char| c.is_ascii_alphabetic())
+            .unwrap();
+        let mut broken = checksummed.clone();
+        let flipped = match broken.as_bytes()[i] {
+            c if c.is_ascii_uppercase() => c.to_ascii_lowercase(),
+            c => c.to_ascii_uppercase(),
+        };
+        broken.replace_range(i..=i, &(flipped as char).to_string());
+        assert_eq!(parse_address(&broken), None);
+
+        // an ed25519 signature of the same length never passes for a secp256k1 one
+        let ed25519 = keypair(1);
+        let public_key = hex::encode(ed25519.public.as_bytes());
+        assert_eq!(
+            SignatureScheme::of_address(&public_key),
+            Some(SignatureScheme::Ed25519)
+        );
+        assert_eq!(
+            SignatureScheme::of_address(&public_key.to_ascii_uppercase()),
+            None
+        );
+        assert_eq!(
+            parse_address(&public_key.to_ascii_uppercase()),
+            Some(public_key.clone())
+        );
+        let signature = Signature::new(SignatureScheme::Secp256k1, &ed25519.sign(&hash).to_bytes());
+        assert!(!signature.verify_address(&public_key, &hash));
+        assert!(Signature::from_bytes(&[0; 10]).is_none());
+    }
+
+    #[test]
+    fn messages_are_never_transactions() {
+        let keypair = keypair(1);
+        let public_key = &hex::encode(keypair.public.as_bytes());
+        let tx = transaction(&keypair, 0);
+
+        // a message crafted to be the exact preimage the transaction's signature covers
+        let mut message = TRANSACTION_DOMAIN.to_vec();
+        message.extend_from_slice(&rlp::encode(&tx));
+        let signature = Signature::new(
+            SignatureScheme::Ed25519,
+            &keypair.sign(&message_hash(&message)).to_bytes(),
+        );
+        assert!(verify_message(public_key, &message, &signature));
+        assert!(!verify_message(public_key, b"something else", &signature));
+
+        let forged = signed(tx.clone(), TransactionSignature::Single(signature));
+        assert_eq!(verify_transactions([&forged]), Err(vec![0]));
+
+        // and transaction signatures aren't valid messages
+        let signature = sign(&keypair, &tx);
+        assert!(!verify_message(public_key, &message, &signature));
+        assert!(!verify_message(
+            public_key,
+            &tx.signature_hash(),
+            &signature
+        ));
     }
 }
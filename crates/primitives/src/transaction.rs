@@ -1,27 +1,161 @@
-use rlp::{Encodable, RlpStream};
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
 use serde::{Deserialize, Serialize};
 
-use crate::{signature::Signature, TxHash};
+use crate::{
+    amount::U256,
+    codec::{decode_list, decode_option, expect_items},
+    multisig::MultisigPolicy,
+    signature::TransactionSignature,
+    TxHash,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransactionSigned {
     pub hash: TxHash,
-    pub signature: Signature,
+    pub signature: TransactionSignature,
     pub transaction: Transaction,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Transaction {
     pub from: String,
-    pub to: String,
     pub nonce: u64,
+    /// Paid by the sender on top of the transferred value.
+    pub fee: U256,
+    pub kind: TransactionKind,
+    /// First block the transaction can be included in.
+    pub valid_after: Option<Timelock>,
+    /// Last block the transaction can be included in.
+    pub valid_until: Option<Timelock>,
+}
+
+/// A point on the chain, either a block height or a unix timestamp in seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Timelock {
+    Height(u64),
+    Timestamp(u64),
+}
+
+impl Timelock {
+    /// Whether a block at `height` with `timestamp` is at or past this point.
+    pub fn is_reached(&self, height: u64, timestamp: u64) -> bool {
+        match *self {
+            Timelock::Height(h) => height >= h,
+            Timelock::Timestamp(t) => timestamp >= t,
+        }
+    }
+
+    /// Whether a block at `height` with `timestamp` is strictly past this point.
+    pub fn is_passed(&self, height: u64, timestamp: u64) -> bool {
+        match *self {
+            Timelock::Height(h) => height > h,
+            Timelock::Timestamp(t) => timestamp > t,
+        }
+    }
 }
 
+/// Whether a transaction can be included in a given block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Validity {
+    NotYetValid,
+    Valid,
+    Expired,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionKind {
+    /// Moves `value` from the sender to `to`.
+    Transfer(Transfer),
+    /// Creates a new multisig account, see [`crate::multisig::multisig_address`] for its address.
+    CreateMultisig(MultisigPolicy),
+    /// Replaces the policy of the multisig account sending the transaction.
+    UpdateMultisig(MultisigPolicy),
+    /// Several transfers that are applied all together or not at all.
+    Batch(Vec<Transfer>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transfer {
+    pub to: String,
+    pub value: U256,
+}
+
+/// Prepended to the encoded transaction before hashing it for signing, so transaction signatures
+/// can't be confused with signatures of anything else, see [`crate::signature::message_hash`].
+pub const TRANSACTION_DOMAIN: &[u8] = b"\x19Felipeum Transaction:\n";
+
 impl Transaction {
+    /// The hash signed by the sender, `keccak256(TRANSACTION_DOMAIN || rlp(transaction))`.
+    ///
+    /// It doesn't commit to a chain ID: a transaction signed for one chain is valid on any other
+    /// where its sender has the same nonce, the local and dev chains included. A key must not
+    /// hold funds on more than one network.
     pub fn signature_hash(&self) -> [u8; 32] {
-        let encoded = rlp::encode(self);
+        let mut encoded = TRANSACTION_DOMAIN.to_vec();
+        encoded.extend_from_slice(&rlp::encode(self));
         keccak256(&encoded)
     }
+
+    /// Total value moved out of the sender's account, `None` if it overflows.
+    pub fn value(&self) -> Option<U256> {
+        match &self.kind {
+            TransactionKind::Transfer(transfer) => Some(transfer.value),
+            TransactionKind::Batch(transfers) => {
+                transfers.iter().try_fold(U256::zero(), |total, transfer| {
+                    total.checked_add(transfer.value)
+                })
+            }
+            TransactionKind::CreateMultisig(_) | TransactionKind::UpdateMultisig(_) => {
+                Some(U256::zero())
+            }
+        }
+    }
+
+    /// Value plus fee, `None` if it overflows.
+    pub fn cost(&self) -> Option<U256> {
+        self.value()?.checked_add(self.fee)
+    }
+
+    /// Whether the sender and recipients are spelled in lowercase, the only form addresses are
+    /// accepted in. Accounts are keyed by address, so one key must not control two accounts
+    /// spelled in different cases.
+    pub fn has_lowercase_addresses(&self) -> bool {
+        let is_lowercase = |address: &str| !address.bytes().any(|b| b.is_ascii_uppercase());
+        let recipients = match &self.kind {
+            TransactionKind::Transfer(transfer) => std::slice::from_ref(transfer),
+            TransactionKind::Batch(transfers) => transfers.as_slice(),
+            TransactionKind::CreateMultisig(_) | TransactionKind::UpdateMultisig(_) => &[],
+        };
+        is_lowercase(&self.from) && recipients.iter().all(|transfer| is_lowercase(&transfer.to))
+    }
+
+    /// Checks the transaction's window against a block at `height` with `timestamp`.
+    pub fn validity(&self, height: u64, timestamp: u64) -> Validity {
+        match (self.valid_after, self.valid_until) {
+            (Some(after), _) if !after.is_reached(height, timestamp) => Validity::NotYetValid,
+            (_, Some(until)) if until.is_passed(height, timestamp) => Validity::Expired,
+            _ => Validity::Valid,
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl Transaction {
+    /// A transfer without fee or timelocks, tests set the other fields they care about.
+    pub fn transfer(from: &str, to: &str, value: u64, nonce: u64) -> Self {
+        Self {
+            from: from.to_string(),
+            nonce,
+            fee: U256::zero(),
+            kind: TransactionKind::Transfer(Transfer {
+                to: to.to_string(),
+                value: U256::from(value),
+            }),
+            valid_after: None,
+            valid_until: None,
+        }
+    }
 }
 
 pub fn keccak256(data: impl AsRef<[u8]>) -> [u8; 32] {
@@ -36,10 +170,125 @@ pub fn keccak256(data: impl AsRef<[u8]>) -> [u8; 32] {
 
 impl Encodable for Transaction {
     fn rlp_append(&self, s: &mut RlpStream) {
-        s.begin_list(3);
+        s.begin_list(6);
         s.append(&self.nonce);
         s.append(&self.from);
+        s.append(&self.fee);
+        s.append(&self.kind);
+        s.append(&self.valid_after);
+        s.append(&self.valid_until);
+    }
+}
+
+impl Decodable for Transaction {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let items = expect_items(rlp, 6)?;
+        let transaction = Self {
+            nonce: items[0].as_val()?,
+            from: items[1].as_val()?,
+            fee: items[2].as_val()?,
+            kind: items[3].as_val()?,
+            valid_after: decode_option(&items[4])?,
+            valid_until: decode_option(&items[5])?,
+        };
+        if !transaction.has_lowercase_addresses() {
+            return Err(DecoderError::Custom("addresses must be lowercase"));
+        }
+        Ok(transaction)
+    }
+}
+
+const HEIGHT: u8 = 0;
+const TIMESTAMP: u8 = 1;
+
+impl Encodable for Timelock {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(2);
+        match self {
+            Timelock::Height(height) => s.append(&HEIGHT).append(height),
+            Timelock::Timestamp(timestamp) => s.append(&TIMESTAMP).append(timestamp),
+        };
+    }
+}
+
+impl Decodable for Timelock {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let items = expect_items(rlp, 2)?;
+        match items[0].as_val::<u8>()? {
+            HEIGHT => Ok(Timelock::Height(items[1].as_val()?)),
+            TIMESTAMP => Ok(Timelock::Timestamp(items[1].as_val()?)),
+            _ => Err(DecoderError::Custom("unknown timelock")),
+        }
+    }
+}
+
+const TRANSFER: u8 = 0;
+const CREATE_MULTISIG: u8 = 1;
+const UPDATE_MULTISIG: u8 = 2;
+const BATCH: u8 = 3;
+
+impl Encodable for TransactionKind {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(2);
+        match self {
+            TransactionKind::Transfer(transfer) => s.append(&TRANSFER).append(transfer),
+            TransactionKind::CreateMultisig(policy) => s.append(&CREATE_MULTISIG).append(policy),
+            TransactionKind::UpdateMultisig(policy) => s.append(&UPDATE_MULTISIG).append(policy),
+            TransactionKind::Batch(transfers) => {
+                s.append(&BATCH).append_list::<Transfer, _>(transfers)
+            }
+        };
+    }
+}
+
+impl Decodable for TransactionKind {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let items = expect_items(rlp, 2)?;
+        match items[0].as_val::<u8>()? {
+            TRANSFER => Ok(TransactionKind::Transfer(items[1].as_val()?)),
+            CREATE_MULTISIG => Ok(TransactionKind::CreateMultisig(items[1].as_val()?)),
+            UPDATE_MULTISIG => Ok(TransactionKind::UpdateMultisig(items[1].as_val()?)),
+            BATCH => Ok(TransactionKind::Batch(decode_list(&items[1])?)),
+            _ => Err(DecoderError::Custom("unknown transaction kind")),
+        }
+    }
+}
+
+impl Encodable for Transfer {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(2);
         s.append(&self.to);
+        s.append(&self.value);
+    }
+}
+
+impl Decodable for Transfer {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let items = expect_items(rlp, 2)?;
+        Ok(Self {
+            to: items[0].as_val()?,
+            value: items[1].as_val()?,
+        })
+    }
+}
+
+impl Encodable for TransactionSigned {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(3);
+        s.append(&self.hash);
+        s.append(&self.signature);
+        s.append(&self.transaction);
+    }
+}
+
+impl Decodable for TransactionSigned {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let items = expect_items(rlp, 3)?;
+        Ok(Self {
+            hash: items[0].as_val()?,
+            signature: items[1].as_val()?,
+            transaction: items[2].as_val()?,
+        })
     }
 }
 
@@ -0,0 +1,239 @@
+//! SLIP-0010 hierarchical deterministic derivation of ed25519 keys.
+//!
+//! A single BIP39 seed yields any number of accounts at `m/44'/COIN_TYPE'/account'`. ed25519 only
+//! supports hardened derivation, so every index of a path is hardened.
+use std::{fmt, str::FromStr};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha512;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+use crate::keypair::Keypair;
+
+/// Coin type used in account paths. Not registered in SLIP-0044, chosen to not collide with the
+/// coins commonly found in the same wallets.
+pub const COIN_TYPE: u32 = 7337;
+
+/// Set on the indexes of hardened derivation steps.
+pub const HARDENED: u32 = 1 << 31;
+
+const PURPOSE: u32 = 44;
+const CURVE_SEED: &[u8] = b"ed25519 seed";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HdError {
+    InvalidPath(String),
+    /// ed25519 keys can't be derived at non-hardened indexes.
+    NotHardened(u32),
+}
+
+impl fmt::Display for HdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HdError::InvalidPath(path) => write!(f, "invalid derivation path {path:?}"),
+            HdError::NotHardened(index) => {
+                write!(
+                    f,
+                    "index {index} is not hardened, ed25519 only supports hardened ones"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for HdError {}
+
+/// A derivation path like `m/44'/7337'/0'`, `H` may be used instead of `'`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    /// Path of the `account`th account, `m/44'/COIN_TYPE'/account'`.
+    pub fn account(account: u32) -> Result<Self, HdError> {
+        if account >= HARDENED {
+            return Err(HdError::NotHardened(account));
+        }
+        Ok(Self(vec![
+            PURPOSE | HARDENED,
+            COIN_TYPE | HARDENED,
+            account | HARDENED,
+        ]))
+    }
+
+    /// Indexes of the path, with [`HARDENED`] set.
+    pub fn indexes(&self) -> &[u32] {
+        &self.0
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = HdError;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let invalid = || HdError::InvalidPath(path.to_string());
+        let mut segments = path.split('/');
+        if segments.next() != Some("m") {
+            return Err(invalid());
+        }
+
+        segments
+            .map(|segment| {
+                let index = segment
+                    .strip_suffix('\'')
+                    .or_else(|| segment.strip_suffix('H'));
+                match index {
+                    Some(index) => match index.parse::<u32>() {
+                        Ok(index) if index < HARDENED => Ok(index | HARDENED),
+                        _ => Err(invalid()),
+                    },
+                    None => Err(segment
+                        .parse()
+                        .map_or_else(|_| invalid(), HdError::NotHardened)),
+                }
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for index in &self.0 {
+            write!(f, "/{}'", index & !HARDENED)?;
+        }
+        Ok(())
+    }
+}
+
+/// A secret key along with the chain code needed to derive its children, wiped from memory on
+/// drop.
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct ExtendedSecretKey {
+    secret: [u8; 32],
+    chain_code: [u8; 32],
+}
+
+impl ExtendedSecretKey {
+    /// The master key of `seed`, usually the 64 byte seed of a BIP39 mnemonic.
+    pub fn from_seed(seed: &[u8]) -> Self {
+        Self::from_hmac(CURVE_SEED, &[seed])
+    }
+
+    /// Derives the hardened child at `index`.
+    pub fn derive_child(&self, index: u32) -> Result<Self, HdError> {
+        if index & HARDENED == 0 {
+            return Err(HdError::NotHardened(index));
+        }
+        Ok(Self::from_hmac(
+            &self.chain_code,
+            &[&[0], &self.secret, &index.to_be_bytes()],
+        ))
+    }
+
+    pub fn derive(&self, path: &DerivationPath) -> Result<Self, HdError> {
+        path.indexes()
+            .iter()
+            .try_fold(self.clone(), |key, index| key.derive_child(*index))
+    }
+
+    pub fn chain_code(&self) -> &[u8; 32] {
+        &self.chain_code
+    }
+
+    pub fn keypair(&self) -> Keypair {
+        Keypair::from_secret(&self.secret).expect("any 32 bytes are a valid ed25519 secret key")
+    }
+
+    fn from_hmac(key: &[u8], data: &[&[u8]]) -> Self {
+        let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("hmac accepts keys of any size");
+        for data in data {
+            mac.update(data);
+        }
+        let mut output = mac.finalize().into_bytes();
+
+        let mut key = Self {
+            secret: [0u8; 32],
+            chain_code: [0u8; 32],
+        };
+        key.secret.copy_from_slice(&output[..32]);
+        key.chain_code.copy_from_slice(&output[32..]);
+        output[..].zeroize();
+        key
+    }
+}
+
+/// Derives the keypair at `path` from `seed`.
+pub fn derive_keypair(seed: &[u8], path: &DerivationPath) -> Result<Keypair, HdError> {
+    Ok(ExtendedSecretKey::from_seed(seed).derive(path)?.keypair())
+}
+
+/// Derives the keypairs of the first `count` accounts of `seed`.
+pub fn derive_accounts(seed: &[u8], count: u32) -> Result<Vec<Keypair>, HdError> {
+    (0..count)
+        .map(|account| derive_keypair(seed, &DerivationPath::account(account)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // test vector 1 for ed25519 from the SLIP-0010 specification
+    #[test]
+    fn slip10_test_vector() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedSecretKey::from_seed(&seed);
+        assert_eq!(
+            hex::encode(master.keypair().export_secret()),
+            "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7"
+        );
+        assert_eq!(
+            hex::encode(master.chain_code()),
+            "90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb"
+        );
+
+        let path: DerivationPath = "m/0'/1H/2'".parse().unwrap();
+        let expected = [
+            (
+                "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3",
+                "8c8a13df77a28f3445213a0f432fde644acaa215fc72dcdf300d5efaa85d350c",
+            ),
+            (
+                "b1d0bad404bf35da785a64ca1ac54b2617211d2777696fbffaf208f746ae84f2",
+                "1932a5270f335bed617d5b935c80aedb1a35bd9fc1e31acafd5372c30f5c1187",
+            ),
+            (
+                "92a5b23c0b8a99e37d07df3fb9966917f5d06e02ddbd909c7e184371463e9fc9",
+                "ae98736566d30ed0e9d2f4486a64bc95740d89c7db33f52121f8ea8f76ff0fc1",
+            ),
+        ];
+        let mut key = master;
+        for (index, (secret, public)) in path.indexes().iter().zip(expected) {
+            key = key.derive_child(*index).unwrap();
+            assert_eq!(hex::encode(key.keypair().export_secret()), secret);
+            assert_eq!(hex::encode(key.keypair().public_key()), public);
+        }
+    }
+
+    #[test]
+    fn derivation_paths() {
+        let path: DerivationPath = "m/44'/7337'/3'".parse().unwrap();
+        assert_eq!(path, DerivationPath::account(3).unwrap());
+        assert_eq!(path.to_string(), "m/44'/7337'/3'");
+        assert_eq!(
+            "m/44'/0".parse::<DerivationPath>(),
+            Err(HdError::NotHardened(0))
+        );
+        for invalid in ["", "44'", "m/", "m/x'", "m/2147483648'"] {
+            assert!(matches!(
+                invalid.parse::<DerivationPath>(),
+                Err(HdError::InvalidPath(_))
+            ));
+        }
+
+        let accounts = derive_accounts(&[7u8; 64], 3).unwrap();
+        assert_ne!(accounts[0].public_key(), accounts[1].public_key());
+        assert_ne!(accounts[1].public_key(), accounts[2].public_key());
+    }
+}
@@ -1,10 +1,15 @@
 use bip39::{Language, Mnemonic, MnemonicType, Seed};
-use ed25519_dalek::Verifier;
 use ed25519_dalek::{ed25519, ExpandedSecretKey, SignatureError};
-use std::error::Error;
+use felipeum_primitives::{signature::message_hash, transaction::Transaction};
+use std::{error::Error, fmt};
 
 use rand::{rngs::OsRng, CryptoRng, RngCore};
+use zeroize::Zeroizing;
 
+use crate::hd::{derive_keypair, DerivationPath};
+
+/// An ed25519 keypair. The secret key is wiped from memory when the keypair is dropped and is
+/// never shown by `Debug` or `Display`.
 pub struct Keypair(ed25519_dalek::Keypair);
 
 impl Keypair {
@@ -20,26 +25,57 @@ impl Keypair {
         Self::generate(&mut rng)
     }
 
+    /// Signs an off-chain `message`, prefixed so the signature can never pass as a transaction's.
     pub fn sign_message(&self, message: &[u8]) -> Result<ed25519::Signature, SignatureError> {
-        let expanded: ExpandedSecretKey = (&self.0.secret).into();
-
-        Ok(expanded.sign(&message, &self.0.public).into())
+        self.sign(&message_hash(message))
     }
 
-    pub fn verify(
+    pub fn verify_message(
         &self,
         message: &[u8],
         signature: &ed25519::Signature,
     ) -> Result<(), SignatureError> {
-        self.0.public.verify(message, signature)
+        self.0
+            .public
+            .verify_strict(&message_hash(message), signature)
+    }
+
+    /// Signs the [`Transaction::signature_hash`] of `transaction`.
+    pub fn sign_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<ed25519::Signature, SignatureError> {
+        self.sign(&transaction.signature_hash())
+    }
+
+    // raw bytes are only signed once put in a domain, never straight from callers
+    fn sign(&self, hash: &[u8]) -> Result<ed25519::Signature, SignatureError> {
+        let expanded: ExpandedSecretKey = (&self.0.secret).into();
+
+        Ok(expanded.sign(hash, &self.0.public))
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, ed25519_dalek::SignatureError> {
         ed25519_dalek::Keypair::from_bytes(bytes).map(Self)
     }
 
-    pub fn secret(&self) -> &[u8] {
-        self.0.secret.as_bytes()
+    /// Rebuilds a keypair from its 32 byte secret key.
+    pub fn from_secret(secret: &[u8]) -> Result<Self, ed25519_dalek::SignatureError> {
+        let secret = ed25519_dalek::SecretKey::from_bytes(secret)?;
+        let public = ed25519_dalek::PublicKey::from(&secret);
+        Ok(Self(ed25519_dalek::Keypair { secret, public }))
+    }
+
+    /// Copies the secret key out of the keypair, for backups and keystores only.
+    ///
+    /// The copy is wiped from memory when dropped, so it must not be copied any further.
+    pub fn export_secret(&self) -> Zeroizing<[u8; ed25519_dalek::SECRET_KEY_LENGTH]> {
+        Zeroizing::new(self.0.secret.to_bytes())
+    }
+
+    /// Same as [`Keypair::export_secret`], hex encoded.
+    pub fn export_secret_hex(&self) -> Zeroizing<String> {
+        Zeroizing::new(hex::encode(self.0.secret.as_bytes()))
     }
 
     pub fn public_key(&self) -> &[u8] {
@@ -47,23 +83,128 @@ impl Keypair {
     }
 }
 
+impl fmt::Debug for Keypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keypair")
+            .field("public_key", &hex::encode(self.public_key()))
+            .field("secret_key", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Shows the public key only.
+impl fmt::Display for Keypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.public_key()))
+    }
+}
+
 pub fn keypair_from_seed(seed: &[u8]) -> Result<Keypair, Box<dyn Error>> {
     if seed.len() < ed25519_dalek::SECRET_KEY_LENGTH {
         return Err("Seed is too short".into());
     }
-    let secret = ed25519_dalek::SecretKey::from_bytes(&seed[..ed25519_dalek::SECRET_KEY_LENGTH])
+    let keypair = Keypair::from_secret(&seed[..ed25519_dalek::SECRET_KEY_LENGTH])
         .map_err(|e| e.to_string())?;
-    let public = ed25519_dalek::PublicKey::from(&secret);
-    let dalek_keypair = ed25519_dalek::Keypair { secret, public };
-    Ok(Keypair(dalek_keypair))
+    Ok(keypair)
 }
 
 pub fn new_keypair() -> Result<Keypair, Box<dyn Error>> {
-    let mnemonic_type = MnemonicType::for_word_count(12)?;
-        let mnemonic = Mnemonic::new(mnemonic_type, Language::English);
-    let passphrase: &str = "";
-    let seed = Seed::new(&mnemonic, &passphrase);
+    let (keypair, _) = new_keypair_with_mnemonic(12, "")?;
+    Ok(keypair)
+}
+
+/// Generates a keypair from a new BIP39 mnemonic of `word_count` words, returned along with it.
+///
+/// The phrase and `passphrase` are all that's needed to recover the key with
+/// [`keypair_from_mnemonic`].
+pub fn new_keypair_with_mnemonic(
+    word_count: usize,
+    passphrase: &str,
+) -> Result<(Keypair, Zeroizing<String>), Box<dyn Error>> {
+    check_word_count(word_count)?;
+    let mnemonic_type = MnemonicType::for_word_count(word_count)?;
+    let mnemonic = Mnemonic::new(mnemonic_type, Language::English);
+    let seed = Seed::new(&mnemonic, passphrase);
     let keypair = keypair_from_seed(seed.as_bytes())?;
 
-    Ok(keypair)
+    Ok((keypair, Zeroizing::new(mnemonic.phrase().to_string())))
+}
+
+/// Recovers the keypair of a 12 or 24 word English BIP39 phrase, checking its checksum.
+pub fn keypair_from_mnemonic(phrase: &str, passphrase: &str) -> Result<Keypair, Box<dyn Error>> {
+    let seed = mnemonic_seed(phrase, passphrase)?;
+    keypair_from_seed(seed.as_bytes())
+}
+
+/// Same as [`keypair_from_mnemonic`], but derives the key at `path` with SLIP-0010.
+pub fn keypair_from_mnemonic_path(
+    phrase: &str,
+    passphrase: &str,
+    path: &DerivationPath,
+) -> Result<Keypair, Box<dyn Error>> {
+    let seed = mnemonic_seed(phrase, passphrase)?;
+    Ok(derive_keypair(seed.as_bytes(), path)?)
+}
+
+fn mnemonic_seed(phrase: &str, passphrase: &str) -> Result<Seed, Box<dyn Error>> {
+    check_word_count(phrase.split_whitespace().count())?;
+    let mnemonic = Mnemonic::from_phrase(phrase, Language::English)?;
+    Ok(Seed::new(&mnemonic, passphrase))
+}
+
+fn check_word_count(word_count: usize) -> Result<(), Box<dyn Error>> {
+    match word_count {
+        12 | 24 => Ok(()),
+        _ => Err(format!("mnemonic must have 12 or 24 words, got {word_count}").into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mnemonic_recovery() {
+        for word_count in [12, 24] {
+            let (keypair, phrase) = new_keypair_with_mnemonic(word_count, "extra").unwrap();
+            assert_eq!(phrase.split_whitespace().count(), word_count);
+
+            let recovered = keypair_from_mnemonic(&phrase, "extra").unwrap();
+            assert_eq!(recovered.public_key(), keypair.public_key());
+
+            // a different passphrase is a different, equally valid, wallet
+            let other = keypair_from_mnemonic(&phrase, "").unwrap();
+            assert_ne!(other.public_key(), keypair.public_key());
+        }
+
+        // the last word carries the checksum, only `about` is valid here
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+                      abandon abandon abandon";
+        assert!(keypair_from_mnemonic(phrase, "").is_err());
+        assert!(keypair_from_mnemonic(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+             abandon about",
+            ""
+        )
+        .is_ok());
+
+        assert!(new_keypair_with_mnemonic(15, "").is_err());
+    }
+
+    #[test]
+    fn secret_is_redacted() {
+        let keypair = Keypair::new();
+        let secret = keypair.export_secret_hex();
+
+        for shown in [format!("{keypair:?}"), keypair.to_string()] {
+            assert!(shown.contains(&hex::encode(keypair.public_key())));
+            assert!(!shown.contains(secret.as_str()));
+        }
+        assert_eq!(
+            Keypair::from_secret(&*keypair.export_secret())
+                .unwrap()
+                .public_key(),
+            keypair.public_key()
+        );
+    }
 }
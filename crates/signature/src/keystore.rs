@@ -0,0 +1,447 @@
+//! Passphrase encrypted keypairs on disk.
+//!
+//! The secret key is encrypted with ChaCha20-Poly1305 under a key derived from the passphrase
+//! with scrypt, and stored as a versioned JSON file named after the account's address.
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use chacha20poly1305::{
+    aead::{Aead, Payload},
+    ChaCha20Poly1305, KeyInit, Nonce,
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::keypair::Keypair;
+
+/// Version of the file format written by [`KeystoreFile::encrypt`].
+pub const KEYSTORE_VERSION: u32 = 1;
+
+const KDF: &str = "scrypt";
+const CIPHER: &str = "chacha20poly1305";
+const SALT_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+const KEY_LENGTH: usize = 32;
+
+/// Files asking for more than this are rejected, decrypting them could exhaust the memory.
+const MAX_LOG_N: u8 = 20;
+
+/// Largest `r * p * 2^log_n` of files we decrypt. scrypt takes `128 * r * 2^log_n` bytes of
+/// memory and time proportional to the whole product, this allows a GiB and a few seconds.
+const MAX_KDF_COST: u128 = 1 << 23;
+
+#[derive(Debug)]
+pub enum KeystoreError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    UnsupportedVersion(u32),
+    UnsupportedKdf(String),
+    UnsupportedCipher(String),
+    InvalidKdfParams,
+    /// The file is corrupted or the passphrase is wrong, the two can't be told apart.
+    Decryption,
+    /// The decrypted key doesn't belong to the address of the file.
+    AddressMismatch,
+    InvalidAddress(String),
+    NotFound(String),
+    AlreadyExists(String),
+}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeystoreError::Io(err) => write!(f, "keystore io error: {err}"),
+            KeystoreError::Json(err) => write!(f, "invalid keystore file: {err}"),
+            KeystoreError::UnsupportedVersion(version) => {
+                write!(f, "unsupported keystore version {version}")
+            }
+            KeystoreError::UnsupportedKdf(kdf) => write!(f, "unsupported kdf {kdf}"),
+            KeystoreError::UnsupportedCipher(cipher) => write!(f, "unsupported cipher {cipher}"),
+            KeystoreError::InvalidKdfParams => write!(f, "invalid kdf parameters"),
+            KeystoreError::Decryption => write!(f, "wrong passphrase or corrupted keystore file"),
+            KeystoreError::AddressMismatch => write!(f, "key doesn't match the keystore address"),
+            KeystoreError::InvalidAddress(address) => write!(f, "invalid address {address}"),
+            KeystoreError::NotFound(address) => write!(f, "no account {address} in keystore"),
+            KeystoreError::AlreadyExists(address) => {
+                write!(f, "account {address} already in keystore")
+            }
+        }
+    }
+}
+
+impl std::error::Error for KeystoreError {}
+
+impl From<io::Error> for KeystoreError {
+    fn from(err: io::Error) -> Self {
+        KeystoreError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for KeystoreError {
+    fn from(err: serde_json::Error) -> Self {
+        KeystoreError::Json(err)
+    }
+}
+
+/// Cost parameters of scrypt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+impl Default for KdfParams {
+    /// 32 MiB of memory, roughly a tenth of a second per attempt.
+    fn default() -> Self {
+        Self {
+            log_n: 15,
+            r: 8,
+            p: 1,
+        }
+    }
+}
+
+impl KdfParams {
+    fn derive_key(
+        &self,
+        passphrase: &str,
+        salt: &[u8],
+    ) -> Result<Zeroizing<[u8; KEY_LENGTH]>, KeystoreError> {
+        if self.log_n > MAX_LOG_N
+            || (u128::from(self.r) * u128::from(self.p)) << self.log_n > MAX_KDF_COST
+        {
+            return Err(KeystoreError::InvalidKdfParams);
+        }
+        let params = scrypt::Params::new(self.log_n, self.r, self.p, KEY_LENGTH)
+            .map_err(|_| KeystoreError::InvalidKdfParams)?;
+
+        let mut key = Zeroizing::new([0u8; KEY_LENGTH]);
+        scrypt::scrypt(passphrase.as_bytes(), salt, &params, key.as_mut())
+            .map_err(|_| KeystoreError::InvalidKdfParams)?;
+        Ok(key)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeystoreCrypto {
+    pub kdf: String,
+    pub kdf_params: KdfParams,
+    /// Hex encoded.
+    pub salt: String,
+    pub cipher: String,
+    /// Hex encoded.
+    pub nonce: String,
+    /// Hex encoded secret key followed by the authentication tag.
+    pub ciphertext: String,
+}
+
+/// The JSON document stored for every account.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeystoreFile {
+    pub version: u32,
+    /// Hex encoded public key, authenticated along with the secret key.
+    pub address: String,
+    pub crypto: KeystoreCrypto,
+}
+
+impl KeystoreFile {
+    pub fn encrypt(
+        keypair: &Keypair,
+        passphrase: &str,
+        kdf_params: KdfParams,
+    ) -> Result<Self, KeystoreError> {
+        let mut salt = [0u8; SALT_LENGTH];
+        let mut nonce = [0u8; NONCE_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let address = hex::encode(keypair.public_key());
+        let key = kdf_params.derive_key(passphrase, &salt)?;
+        let secret = keypair.export_secret();
+        let ciphertext = ChaCha20Poly1305::new(key.as_ref().into())
+            .encrypt(
+                &Nonce::from(nonce),
+                Payload {
+                    msg: secret.as_ref(),
+                    aad: address.as_bytes(),
+                },
+            )
+            .expect("a secret key is far below the cipher's message size limit");
+
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            address,
+            crypto: KeystoreCrypto {
+                kdf: KDF.to_string(),
+                kdf_params,
+                salt: hex::encode(salt),
+                cipher: CIPHER.to_string(),
+                nonce: hex::encode(nonce),
+                ciphertext: hex::encode(ciphertext),
+            },
+        })
+    }
+
+    pub fn decrypt(&self, passphrase: &str) -> Result<Keypair, KeystoreError> {
+        if self.version != KEYSTORE_VERSION {
+            return Err(KeystoreError::UnsupportedVersion(self.version));
+        }
+        let crypto = &self.crypto;
+        if crypto.kdf != KDF {
+            return Err(KeystoreError::UnsupportedKdf(crypto.kdf.clone()));
+        }
+        if crypto.cipher != CIPHER {
+            return Err(KeystoreError::UnsupportedCipher(crypto.cipher.clone()));
+        }
+
+        let salt = hex::decode(&crypto.salt).map_err(|_| KeystoreError::Decryption)?;
+        let nonce: [u8; NONCE_LENGTH] = hex::decode(&crypto.nonce)
+            .ok()
+            .and_then(|nonce| nonce.try_into().ok())
+            .ok_or(KeystoreError::Decryption)?;
+        let ciphertext = hex::decode(&crypto.ciphertext).map_err(|_| KeystoreError::Decryption)?;
+
+        let key = crypto.kdf_params.derive_key(passphrase, &salt)?;
+        let secret = ChaCha20Poly1305::new(key.as_ref().into())
+            .decrypt(
+                &Nonce::from(nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: self.address.as_bytes(),
+                },
+            )
+            .map(Zeroizing::new)
+            .map_err(|_| KeystoreError::Decryption)?;
+
+        let keypair = Keypair::from_secret(&secret).map_err(|_| KeystoreError::Decryption)?;
+        if hex::encode(keypair.public_key()) != self.address {
+            return Err(KeystoreError::AddressMismatch);
+        }
+        Ok(keypair)
+    }
+}
+
+/// A directory holding one [`KeystoreFile`] per account.
+#[derive(Clone, Debug)]
+pub struct Keystore {
+    dir: PathBuf,
+    kdf_params: KdfParams,
+}
+
+impl Keystore {
+    /// Opens the keystore at `dir`, creating the directory if needed.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, KeystoreError> {
+        Self::with_kdf_params(dir, KdfParams::default())
+    }
+
+    /// Same as [`Keystore::open`], but new accounts are encrypted with `kdf_params`.
+    pub fn with_kdf_params(
+        dir: impl Into<PathBuf>,
+        kdf_params: KdfParams,
+    ) -> Result<Self, KeystoreError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir, kdf_params })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Addresses of all accounts, sorted.
+    pub fn list(&self) -> Result<Vec<String>, KeystoreError> {
+        let mut addresses = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+                continue;
+            }
+            if let Some(address) = path.file_stem().and_then(|stem| stem.to_str()) {
+                if is_address(address) {
+                    addresses.push(address.to_string());
+                }
+            }
+        }
+        addresses.sort();
+        Ok(addresses)
+    }
+
+    /// Encrypts `keypair` with `passphrase` and adds it, returning its address.
+    pub fn insert(&self, keypair: &Keypair, passphrase: &str) -> Result<String, KeystoreError> {
+        self.import(KeystoreFile::encrypt(keypair, passphrase, self.kdf_params)?)
+    }
+
+    /// Decrypts the account at `address`.
+    pub fn unlock(&self, address: &str, passphrase: &str) -> Result<Keypair, KeystoreError> {
+        self.export(address)?.decrypt(passphrase)
+    }
+
+    /// Adds an already encrypted account, returning its address.
+    pub fn import(&self, file: KeystoreFile) -> Result<String, KeystoreError> {
+        let path = self.path(&file.address)?;
+        if path.exists() {
+            return Err(KeystoreError::AlreadyExists(file.address));
+        }
+
+        // written next to its final place and renamed, so a crash never leaves half a file
+        let tmp = path.with_extension("json.tmp");
+        // a leftover of a crash may have other permissions, the file is always created anew
+        match fs::remove_file(&tmp) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        // only readable by the owner from the start, not after the secret is written
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut tmp_file = options.open(&tmp)?;
+        tmp_file.write_all(&serde_json::to_vec_pretty(&file)?)?;
+        tmp_file.sync_all()?;
+        fs::rename(&tmp, &path)?;
+
+        Ok(file.address)
+    }
+
+    /// Returns the still encrypted account at `address`.
+    pub fn export(&self, address: &str) -> Result<KeystoreFile, KeystoreError> {
+        let path = self.path(address)?;
+        let bytes = fs::read(&path).map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => KeystoreError::NotFound(address.to_string()),
+            _ => err.into(),
+        })?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    pub fn delete(&self, address: &str) -> Result<(), KeystoreError> {
+        fs::remove_file(self.path(address)?).map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => KeystoreError::NotFound(address.to_string()),
+            _ => err.into(),
+        })
+    }
+
+    // addresses come from users, checking them keeps paths inside the keystore directory
+    fn path(&self, address: &str) -> Result<PathBuf, KeystoreError> {
+        if !is_address(address) {
+            return Err(KeystoreError::InvalidAddress(address.to_string()));
+        }
+        Ok(self.dir.join(format!("{address}.json")))
+    }
+}
+
+fn is_address(address: &str) -> bool {
+    address.len() == 2 * ed25519_dalek::PUBLIC_KEY_LENGTH
+        && address
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // keeps the tests fast, the default parameters take a noticeable time per derivation
+    const TEST_PARAMS: KdfParams = KdfParams {
+        log_n: 4,
+        r: 8,
+        p: 1,
+    };
+
+    #[test]
+    fn encrypt_decrypt() {
+        let keypair = Keypair::new();
+        let file = KeystoreFile::encrypt(&keypair, "hunter2", TEST_PARAMS).unwrap();
+
+        let json = serde_json::to_string(&file).unwrap();
+        let file: KeystoreFile = serde_json::from_str(&json).unwrap();
+        let decrypted = file.decrypt("hunter2").unwrap();
+        assert_eq!(decrypted.public_key(), keypair.public_key());
+        assert_eq!(*decrypted.export_secret(), *keypair.export_secret());
+
+        assert!(matches!(
+            file.decrypt("hunter3"),
+            Err(KeystoreError::Decryption)
+        ));
+
+        // the address is authenticated, pointing the file at another account breaks it
+        let mut tampered = file.clone();
+        tampered.address = hex::encode(Keypair::new().public_key());
+        assert!(matches!(
+            tampered.decrypt("hunter2"),
+            Err(KeystoreError::Decryption)
+        ));
+
+        let mut expensive = file;
+        expensive.crypto.kdf_params.log_n = 40;
+        assert!(matches!(
+            expensive.decrypt("hunter2"),
+            Err(KeystoreError::InvalidKdfParams)
+        ));
+        // a small n doesn't make up for huge r or p
+        for (r, p) in [
+            (u32::MAX, 1),
+            (8, u32::MAX),
+            (u32::MAX, u32::MAX),
+            (1 << 20, 1 << 10),
+        ] {
+            expensive.crypto.kdf_params = KdfParams { log_n: 4, r, p };
+            assert!(matches!(
+                expensive.decrypt("hunter2"),
+                Err(KeystoreError::InvalidKdfParams)
+            ));
+        }
+        assert!(KdfParams::default()
+            .derive_key("", &[0; SALT_LENGTH])
+            .is_ok());
+    }
+
+    #[test]
+    fn keystore_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = Keystore::with_kdf_params(dir.path(), TEST_PARAMS).unwrap();
+        let keypair = Keypair::new();
+
+        let address = keystore.insert(&keypair, "pass").unwrap();
+        assert_eq!(keystore.list().unwrap(), vec![address.clone()]);
+        assert_eq!(
+            keystore.unlock(&address, "pass").unwrap().public_key(),
+            keypair.public_key()
+        );
+
+        let exported = keystore.export(&address).unwrap();
+        assert!(matches!(
+            keystore.import(exported.clone()),
+            Err(KeystoreError::AlreadyExists(_))
+        ));
+
+        keystore.delete(&address).unwrap();
+        assert!(keystore.list().unwrap().is_empty());
+        assert!(matches!(
+            keystore.unlock(&address, "pass"),
+            Err(KeystoreError::NotFound(_))
+        ));
+
+        keystore.import(exported).unwrap();
+        assert_eq!(keystore.list().unwrap(), vec![address.clone()]);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let path = dir.path().join(format!("{address}.json"));
+            let mode = fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        assert!(matches!(
+            keystore.export("../../etc/passwd"),
+            Err(KeystoreError::InvalidAddress(_))
+        ));
+    }
+}
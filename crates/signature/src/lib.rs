@@ -1 +1,7 @@
+pub mod hd;
 pub mod keypair;
+pub mod keystore;
+#[cfg(unix)]
+pub mod remote_signer;
+pub mod secp256k1;
+pub mod signer;
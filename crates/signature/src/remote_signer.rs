@@ -0,0 +1,249 @@
+//! A [`Signer`] whose key lives in a separate signing daemon, reached over a unix socket.
+//!
+//! Every request opens a connection, writes one JSON [`SignerRequest`] line and reads one JSON
+//! [`SignerResponse`] line back. [`serve`] implements the daemon side for any [`Signer`], which
+//! is what a daemon embeds and what tests use as a stand-in.
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use felipeum_primitives::{
+    signature::{public_key_address, Signature, SignatureScheme, TransactionSignature},
+    transaction::{Transaction, TransactionSigned},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+};
+
+use crate::signer::{transaction_signed, Signer, SignerError};
+
+/// Longest line either side reads, a transaction is far below it.
+const MAX_MESSAGE_SIZE: u64 = 1024 * 1024;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "camelCase")]
+pub enum SignerRequest {
+    PublicKey,
+    SignTransaction { transaction: Transaction },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SignerResponse {
+    /// Hex encoded.
+    PublicKey(String),
+    /// Hex encoded signature of the transaction's signature hash.
+    Signature(String),
+    Error(String),
+}
+
+pub struct RemoteSigner {
+    socket: PathBuf,
+    public_key: Vec<u8>,
+    scheme: SignatureScheme,
+    address: String,
+}
+
+impl RemoteSigner {
+    /// Connects to the daemon listening at `socket` and fetches the public key it signs with.
+    pub async fn connect(socket: impl Into<PathBuf>) -> Result<Self, SignerError> {
+        let socket = socket.into();
+        let public_key = match send_request(&socket, &SignerRequest::PublicKey).await? {
+            SignerResponse::PublicKey(public_key) => hex::decode(public_key)
+                .map_err(|err| SignerError::Protocol(format!("invalid public key: {err}")))?,
+            response => return Err(unexpected(response)),
+        };
+        let address = public_key_address(&public_key)
+            .ok_or_else(|| SignerError::Protocol("invalid public key".to_string()))?;
+        let scheme = SignatureScheme::of_address(&address)
+            .ok_or_else(|| SignerError::Protocol("invalid public key".to_string()))?;
+
+        Ok(Self {
+            socket,
+            public_key,
+            scheme,
+            address,
+        })
+    }
+
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+}
+
+#[async_trait]
+impl Signer for RemoteSigner {
+    fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    fn scheme(&self) -> SignatureScheme {
+        self.scheme
+    }
+
+    fn address(&self) -> String {
+        self.address.clone()
+    }
+
+    async fn sign_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<TransactionSigned, SignerError> {
+        let request = SignerRequest::SignTransaction {
+            transaction: transaction.clone(),
+        };
+        let signature = match send_request(&self.socket, &request).await? {
+            SignerResponse::Signature(signature) => hex::decode(signature)
+                .map_err(|err| SignerError::Protocol(format!("invalid signature: {err}")))?,
+            response => return Err(unexpected(response)),
+        };
+
+        // the daemon isn't trusted to sign what it was asked to with the key it announced
+        let signature = Signature::new(self.scheme, &signature);
+        if !signature.verify_address(&self.address, &transaction.signature_hash()) {
+            return Err(SignerError::InvalidSignature);
+        }
+        Ok(transaction_signed(transaction, signature))
+    }
+}
+
+async fn send_request(
+    socket: &Path,
+    request: &SignerRequest,
+) -> Result<SignerResponse, SignerError> {
+    let mut stream = BufReader::new(UnixStream::connect(socket).await?);
+    write_message(&mut stream, request).await?;
+    read_message(&mut stream).await
+}
+
+fn unexpected(response: SignerResponse) -> SignerError {
+    match response {
+        SignerResponse::Error(msg) => SignerError::Remote(msg),
+        response => SignerError::Protocol(format!("unexpected response {response:?}")),
+    }
+}
+
+/// Answers requests on `listener` with `signer` until accepting a connection fails.
+pub async fn serve(listener: UnixListener, signer: Arc<dyn Signer>) -> std::io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let signer = signer.clone();
+        tokio::spawn(async move {
+            let mut stream = BufReader::new(stream);
+            let response = match read_message::<SignerRequest>(&mut stream).await {
+                Ok(request) => handle(signer.as_ref(), request).await,
+                Err(err) => SignerResponse::Error(err.to_string()),
+            };
+            // the client went away, nothing is left to do with the connection either way
+            let _ = write_message(&mut stream, &response).await;
+        });
+    }
+}
+
+async fn handle(signer: &dyn Signer, request: SignerRequest) -> SignerResponse {
+    match request {
+        SignerRequest::PublicKey => SignerResponse::PublicKey(hex::encode(signer.public_key())),
+        SignerRequest::SignTransaction { transaction } => {
+            match signer.sign_transaction(&transaction).await {
+                Ok(TransactionSigned {
+                    signature: TransactionSignature::Single(signature),
+                    ..
+                }) => SignerResponse::Signature(hex::encode(signature.as_bytes())),
+                Ok(_) => SignerResponse::Error("expected a single signature".to_string()),
+                Err(err) => SignerResponse::Error(err.to_string()),
+            }
+        }
+    }
+}
+
+async fn read_message<T: DeserializeOwned>(
+    stream: &mut BufReader<UnixStream>,
+) -> Result<T, SignerError> {
+    let mut line = String::new();
+    let read = (&mut *stream)
+        .take(MAX_MESSAGE_SIZE)
+        .read_line(&mut line)
+        .await?;
+    if read == 0 || !line.ends_with('\n') {
+        return Err(SignerError::Protocol("truncated message".to_string()));
+    }
+
+    serde_json::from_str(&line).map_err(|err| SignerError::Protocol(err.to_string()))
+}
+
+async fn write_message<T: Serialize>(
+    stream: &mut BufReader<UnixStream>,
+    message: &T,
+) -> Result<(), SignerError> {
+    let mut line =
+        serde_json::to_vec(message).map_err(|err| SignerError::Protocol(err.to_string()))?;
+    line.push(b'\n');
+    stream.get_mut().write_all(&line).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use felipeum_primitives::signature::verify_transactions;
+
+    use super::*;
+    use crate::{
+        keypair::Keypair,
+        keystore::{KdfParams, Keystore},
+        secp256k1::Secp256k1Keypair,
+        signer::KeystoreSigner,
+    };
+
+    // the stand-in daemon signs with a keystore account, like a real one would
+    #[tokio::test]
+    async fn remote_signer_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let kdf_params = KdfParams {
+            log_n: 4,
+            r: 8,
+            p: 1,
+        };
+        let keystore = Keystore::with_kdf_params(dir.path().join("keys"), kdf_params).unwrap();
+        let keypair = Keypair::new();
+        let public_key = keypair.public_key().to_vec();
+        let address = keystore.insert(&keypair, "pass").unwrap();
+        let daemon = KeystoreSigner::new(keystore, &address, "pass".to_string()).unwrap();
+
+        let socket = dir.path().join("signer.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        tokio::spawn(serve(listener, Arc::new(daemon)));
+
+        let signer = RemoteSigner::connect(&socket).await.unwrap();
+        assert_eq!(signer.public_key(), public_key.as_slice());
+
+        let transaction = Transaction::transfer(&hex::encode(&public_key), "bob", 10, 0);
+        let signed = signer.sign_transaction(&transaction).await.unwrap();
+        assert_eq!(signed.hash, hex::encode(transaction.signature_hash()));
+        let TransactionSignature::Single(signature) = signed.signature else {
+            panic!("expected a single signature");
+        };
+        assert!(signature.verify(&public_key, &transaction.signature_hash()));
+    }
+
+    #[tokio::test]
+    async fn remote_signer_checks_secp256k1_signatures() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypair = Secp256k1Keypair::new();
+        let address = keypair.address().to_string();
+
+        let socket = dir.path().join("signer.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        tokio::spawn(serve(listener, Arc::new(keypair)));
+
+        let signer = RemoteSigner::connect(&socket).await.unwrap();
+        assert_eq!(signer.address(), address);
+
+        let transaction = Transaction::transfer(&address, "bob", 10, 0);
+        let signed = signer.sign_transaction(&transaction).await.unwrap();
+        assert_eq!(verify_transactions([&signed]), Ok(()));
+    }
+}
@@ -0,0 +1,205 @@
+//! secp256k1 ECDSA keys, for accounts held by Ethereum tooling.
+//!
+//! Signatures are recoverable, so transactions carry no public key: the address is recovered
+//! from the signature and compared with the sender, see [`Signature::verify_address`].
+use std::fmt;
+
+use async_trait::async_trait;
+use felipeum_primitives::{
+    signature::{message_hash, secp256k1_address, Signature, SignatureScheme},
+    transaction::{Transaction, TransactionSigned},
+};
+use k256::ecdsa::{self, SigningKey};
+use rand::{rngs::OsRng, CryptoRng, RngCore};
+use zeroize::Zeroizing;
+
+use crate::signer::{transaction_signed, Signer, SignerError};
+
+/// A secp256k1 keypair. The secret key is wiped from memory when the keypair is dropped and is
+/// never shown by `Debug` or `Display`.
+pub struct Secp256k1Keypair {
+    secret: SigningKey,
+    /// SEC1 compressed.
+    public_key: Vec<u8>,
+    address: String,
+}
+
+impl Secp256k1Keypair {
+    pub fn generate<R>(csprng: &mut R) -> Self
+    where
+        R: CryptoRng + RngCore,
+    {
+        let mut secret = Zeroizing::new([0u8; 32]);
+        loop {
+            // all but a negligible share of 32 byte strings are valid scalars
+            csprng.fill_bytes(secret.as_mut());
+            if let Ok(keypair) = Self::from_secret(secret.as_ref()) {
+                return keypair;
+            }
+        }
+    }
+
+    pub fn new() -> Self {
+        Self::generate(&mut OsRng)
+    }
+
+    /// Rebuilds a keypair from its 32 byte secret key.
+    pub fn from_secret(secret: &[u8]) -> Result<Self, ecdsa::Error> {
+        let secret = SigningKey::from_slice(secret)?;
+        let verifying_key = secret.verifying_key();
+        Ok(Self {
+            public_key: verifying_key.to_encoded_point(true).as_bytes().to_vec(),
+            address: secp256k1_address(verifying_key),
+            secret,
+        })
+    }
+
+    /// Copies the secret key out of the keypair, for backups and keystores only.
+    ///
+    /// The copy is wiped from memory when dropped, so it must not be copied any further.
+    pub fn export_secret(&self) -> Zeroizing<[u8; 32]> {
+        Zeroizing::new(self.secret.to_bytes().into())
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Signs an off-chain `message`, prefixed so the signature can never pass as a transaction's.
+    pub fn sign_message(&self, message: &[u8]) -> Result<Signature, ecdsa::Error> {
+        self.sign(&message_hash(message))
+    }
+
+    /// Signs the [`Transaction::signature_hash`] of `transaction`.
+    pub fn sign_transaction(&self, transaction: &Transaction) -> Result<Signature, ecdsa::Error> {
+        self.sign(&transaction.signature_hash())
+    }
+
+    /// `r || s || v` with a low `s`, as [`Signature::recover_address`] expects it.
+    fn sign(&self, hash: &[u8]) -> Result<Signature, ecdsa::Error> {
+        let (signature, recovery_id) = self.secret.sign_prehash_recoverable(hash)?;
+        let (signature, recovery_id) = match signature.normalize_s() {
+            // negating s flips the parity of the point recovered from r
+            Some(normalized) => (
+                normalized,
+                ecdsa::RecoveryId::new(!recovery_id.is_y_odd(), recovery_id.is_x_reduced()),
+            ),
+            None => (signature, recovery_id),
+        };
+
+        let mut bytes = signature.to_bytes().to_vec();
+        bytes.push(recovery_id.to_byte());
+        Ok(Signature::new(SignatureScheme::Secp256k1, &bytes))
+    }
+}
+
+impl Default for Secp256k1Keypair {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Secp256k1Keypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Secp256k1Keypair")
+            .field("address", &self.address)
+            .field("secret_key", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Shows the address only.
+impl fmt::Display for Secp256k1Keypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.address)
+    }
+}
+
+#[async_trait]
+impl Signer for Secp256k1Keypair {
+    fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    fn scheme(&self) -> SignatureScheme {
+        SignatureScheme::Secp256k1
+    }
+
+    fn address(&self) -> String {
+        self.address.clone()
+    }
+
+    async fn sign_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<TransactionSigned, SignerError> {
+        let signature = Secp256k1Keypair::sign_transaction(self, transaction)
+            .map_err(|err| SignerError::Protocol(err.to_string()))?;
+        Ok(transaction_signed(transaction, signature))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use felipeum_primitives::signature::{verify_message, verify_transactions};
+
+    use super::*;
+    use crate::keypair::Keypair;
+
+    #[tokio::test]
+    async fn secp256k1_accounts() {
+        // the address of this key is well known from the web3 documentation
+        let secret =
+            hex::decode("4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318")
+                .unwrap();
+        let keypair = Secp256k1Keypair::from_secret(&secret).unwrap();
+        assert_eq!(
+            keypair.address(),
+            "0x2c7536e3605d9c16a7a3d7b1898e529396a65c23"
+        );
+        assert_eq!(
+            SignatureScheme::of_address(keypair.address()),
+            Some(SignatureScheme::Secp256k1)
+        );
+
+        let signature = keypair.sign_message(b"hello").unwrap();
+        assert_eq!(signature.scheme(), SignatureScheme::Secp256k1);
+        assert!(verify_message(keypair.address(), b"hello", &signature));
+        assert!(!verify_message(keypair.address(), b"bye", &signature));
+        let other = Secp256k1Keypair::new();
+        assert!(!verify_message(other.address(), b"hello", &signature));
+
+        // an ed25519 and a secp256k1 sender in the same batch
+        let ed25519 = Keypair::new();
+        let transactions = [
+            (keypair.address().to_string(), &keypair as &dyn Signer),
+            (hex::encode(ed25519.public_key()), &other as &dyn Signer),
+        ];
+        let mut signed = Vec::new();
+        for (from, signer) in transactions {
+            let transaction = Transaction::transfer(&from, "bob", 10, 0);
+            signed.push(signer.sign_transaction(&transaction).await.unwrap());
+        }
+        // the second one is signed by a secp256k1 key, not the ed25519 sender
+        assert_eq!(verify_transactions(&signed), Err(vec![1]));
+        signed.pop();
+        assert_eq!(verify_transactions(&signed), Ok(()));
+
+        // the high s twin of a signature recovers a key too, but is rejected
+        let mut bytes = signature.as_bytes().to_vec();
+        let high = k256::ecdsa::Signature::from_slice(&bytes[..64]).unwrap();
+        let (r, s) = high.split_scalars();
+        let high = k256::ecdsa::Signature::from_scalars(r, -*s).unwrap();
+        bytes[..64].copy_from_slice(&high.to_bytes());
+        bytes[64] ^= 1;
+        assert!(!verify_message(
+            keypair.address(),
+            b"hello",
+            &Signature::new(SignatureScheme::Secp256k1, &bytes)
+        ));
+    }
+}
@@ -0,0 +1,197 @@
+//! Signing of transactions behind a common interface, wherever the key lives.
+use std::{fmt, io, sync::Arc};
+
+use async_trait::async_trait;
+use ed25519_dalek::SignatureError;
+use felipeum_primitives::{
+    signature::{public_key_address, Signature, SignatureScheme, TransactionSignature},
+    transaction::{Transaction, TransactionSigned},
+};
+use zeroize::Zeroizing;
+
+use crate::{
+    keypair::Keypair,
+    keystore::{Keystore, KeystoreError},
+};
+
+#[derive(Debug)]
+pub enum SignerError {
+    Signing(SignatureError),
+    Keystore(KeystoreError),
+    Io(io::Error),
+    /// The remote signer sent something that isn't a valid response.
+    Protocol(String),
+    /// The remote signer refused or failed to sign.
+    Remote(String),
+    /// The remote signer returned a signature that doesn't verify against its public key.
+    InvalidSignature,
+}
+
+impl fmt::Display for SignerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignerError::Signing(err) => write!(f, "signing failed: {err}"),
+            SignerError::Keystore(err) => write!(f, "{err}"),
+            SignerError::Io(err) => write!(f, "remote signer io error: {err}"),
+            SignerError::Protocol(msg) => write!(f, "remote signer protocol error: {msg}"),
+            SignerError::Remote(msg) => write!(f, "remote signer error: {msg}"),
+            SignerError::InvalidSignature => write!(f, "remote signer returned a bad signature"),
+        }
+    }
+}
+
+impl std::error::Error for SignerError {}
+
+impl From<SignatureError> for SignerError {
+    fn from(err: SignatureError) -> Self {
+        SignerError::Signing(err)
+    }
+}
+
+impl From<KeystoreError> for SignerError {
+    fn from(err: KeystoreError) -> Self {
+        SignerError::Keystore(err)
+    }
+}
+
+impl From<io::Error> for SignerError {
+    fn from(err: io::Error) -> Self {
+        SignerError::Io(err)
+    }
+}
+
+/// Something holding a key that can sign transactions.
+#[async_trait]
+pub trait Signer: Send + Sync {
+    fn public_key(&self) -> &[u8];
+
+    /// Scheme of the key, and of the signatures it makes.
+    fn scheme(&self) -> SignatureScheme;
+
+    /// Address of the account the signer signs for, derived from the public key of either
+    /// scheme. The hex encoded public key if it's of neither, which no account can have.
+    fn address(&self) -> String {
+        public_key_address(self.public_key()).unwrap_or_else(|| hex::encode(self.public_key()))
+    }
+
+    /// Signs `transaction` on behalf of the account of [`Signer::public_key`].
+    async fn sign_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<TransactionSigned, SignerError>;
+}
+
+#[async_trait]
+impl<S: Signer + ?Sized> Signer for Arc<S> {
+    fn public_key(&self) -> &[u8] {
+        (**self).public_key()
+    }
+
+    fn scheme(&self) -> SignatureScheme {
+        (**self).scheme()
+    }
+
+    fn address(&self) -> String {
+        (**self).address()
+    }
+
+    async fn sign_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<TransactionSigned, SignerError> {
+        (**self).sign_transaction(transaction).await
+    }
+}
+
+/// Wraps a single signature of `transaction`, of either scheme, into a signed transaction.
+pub fn transaction_signed(transaction: &Transaction, signature: Signature) -> TransactionSigned {
+    TransactionSigned {
+        hash: hex::encode(transaction.signature_hash()),
+        signature: TransactionSignature::Single(signature),
+        transaction: transaction.clone(),
+    }
+}
+
+/// Signs with a keypair held in memory.
+pub struct LocalSigner {
+    keypair: Keypair,
+}
+
+impl LocalSigner {
+    pub fn new(keypair: Keypair) -> Self {
+        Self { keypair }
+    }
+}
+
+#[async_trait]
+impl Signer for LocalSigner {
+    fn public_key(&self) -> &[u8] {
+        self.keypair.public_key()
+    }
+
+    fn scheme(&self) -> SignatureScheme {
+        SignatureScheme::Ed25519
+    }
+
+    async fn sign_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<TransactionSigned, SignerError> {
+        let signature = self.keypair.sign_transaction(transaction)?;
+        Ok(transaction_signed(
+            transaction,
+            Signature::new(SignatureScheme::Ed25519, &signature.to_bytes()),
+        ))
+    }
+}
+
+/// Signs with an account of a [`Keystore`], decrypted only for the time of each signature.
+pub struct KeystoreSigner {
+    keystore: Keystore,
+    address: String,
+    public_key: Vec<u8>,
+    passphrase: Zeroizing<String>,
+}
+
+impl KeystoreSigner {
+    /// Checks `passphrase` unlocks `address` before returning the signer.
+    pub fn new(keystore: Keystore, address: &str, passphrase: String) -> Result<Self, SignerError> {
+        let passphrase = Zeroizing::new(passphrase);
+        let keypair = keystore.unlock(address, &passphrase)?;
+        Ok(Self {
+            keystore,
+            address: address.to_string(),
+            public_key: keypair.public_key().to_vec(),
+            passphrase,
+        })
+    }
+}
+
+#[async_trait]
+impl Signer for KeystoreSigner {
+    fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    // the keystore only holds ed25519 keys
+    fn scheme(&self) -> SignatureScheme {
+        SignatureScheme::Ed25519
+    }
+
+    async fn sign_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<TransactionSigned, SignerError> {
+        // scrypt is deliberately slow, keep it off the async workers
+        let keystore = self.keystore.clone();
+        let address = self.address.clone();
+        let passphrase = self.passphrase.clone();
+        let keypair = tokio::task::spawn_blocking(move || keystore.unlock(&address, &passphrase))
+            .await
+            .map_err(|err| SignerError::Io(io::Error::other(err)))??;
+
+        LocalSigner::new(keypair)
+            .sign_transaction(transaction)
+            .await
+    }
+}
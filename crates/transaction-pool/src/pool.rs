@@ -1,7 +1,16 @@
-use std::{collections::BTreeMap, sync::Arc};
-
-use felipeum_primitives::transaction::{TransactionId, TransactionSigned};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap},
+    sync::Arc,
+};
+
+use felipeum_primitives::{
+    amount::U256,
+    codec::{encode, expect_items, Codec, MAX_TRANSACTION_SIZE},
+    transaction::{TransactionId, TransactionSigned, Validity},
+};
 use parking_lot::{Mutex, RwLock};
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
 
@@ -9,6 +18,8 @@ use tokio::sync::mpsc;
 #[derive(Debug, Clone)]
 pub struct OnNewBlockEvent {
     pub hash: String,
+    pub number: u64,
+    pub timestamp: u64,
     pub mined_transactions: Vec<PoolTransaction>,
 }
 
@@ -17,6 +28,10 @@ pub struct OnNewBlockEvent {
 pub struct OnNewBlockOutcome {
     pub block_hash: String,
     pub mined: Vec<PoolTransaction>,
+    /// Queued transactions that can be included from the next block on.
+    pub promoted: Vec<PoolTransaction>,
+    /// Transactions whose validity window closed.
+    pub expired: Vec<PoolTransaction>,
 }
 
 #[derive(Debug, Clone)]
@@ -49,6 +64,14 @@ impl Pool {
         self.pool.get_all()
     }
 
+    pub fn get_queued(&self) -> Vec<Arc<PoolTransaction>> {
+        self.pool.get_queued()
+    }
+
+    pub fn best_transactions(&self) -> Vec<Arc<PoolTransaction>> {
+        self.pool.best_transactions()
+    }
+
     pub fn get(&self, key: TransactionId) -> Option<PoolTransaction> {
         self.pool.get(key)
     }
@@ -130,6 +153,14 @@ impl PoolInner {
         self.pool.read().get_all()
     }
 
+    pub fn get_queued(&self) -> Vec<Arc<PoolTransaction>> {
+        self.pool.read().get_queued()
+    }
+
+    pub fn best_transactions(&self) -> Vec<Arc<PoolTransaction>> {
+        self.pool.read().best_transactions()
+    }
+
     pub fn on_new_transaction(&self, event: NewTransactionEvent) {
         let mut transaction_listeners = self.transaction_listener.lock();
 
@@ -150,7 +181,12 @@ impl PoolInner {
 pub struct PoolTransaction {
     pub transaction: TransactionSigned,
     pub transaction_id: TransactionId,
-    // todo: add origin, cost, etc..
+    pub fee: U256,
+    /// Value plus fee, saturated if it doesn't fit; such a transaction can never be executed.
+    pub cost: U256,
+    /// Size of the encoded transaction, a batch counts as a single entry.
+    pub size: usize,
+    // todo: add origin
 }
 
 impl From<TransactionSigned> for PoolTransaction {
@@ -160,12 +196,35 @@ impl From<TransactionSigned> for PoolTransaction {
             transaction.transaction.nonce,
         );
         Self {
+            fee: transaction.transaction.fee,
+            cost: transaction.transaction.cost().unwrap_or(U256::MAX),
+            size: encode(&transaction).len(),
             transaction,
             transaction_id,
         }
     }
 }
 
+// the transaction id is derived from the transaction, so only the transaction goes on the wire
+impl Encodable for PoolTransaction {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(1);
+        s.append(&self.transaction);
+    }
+}
+
+impl Decodable for PoolTransaction {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let items = expect_items(rlp, 1)?;
+        let transaction: TransactionSigned = items[0].as_val()?;
+        Ok(Self::from(transaction))
+    }
+}
+
+impl Codec for PoolTransaction {
+    const MAX_SIZE: usize = MAX_TRANSACTION_SIZE;
+}
+
 #[derive(Clone, Debug)]
 pub struct NewTransactionEvent {
     pub transaction: PoolTransaction,
@@ -174,63 +233,296 @@ pub struct NewTransactionEvent {
 #[derive(Debug)]
 pub enum PoolError {
     DiscardedOnInsert(String),
+    /// The transaction's validity window already closed.
+    Expired(String),
+    /// Adding the transaction would grow the pool past [`MAX_POOL_SIZE`].
+    PoolFull(String),
 }
 
 impl PoolError {
     pub fn hash(&self) -> String {
         match self {
             PoolError::DiscardedOnInsert(hash) => hash.to_string(),
+            PoolError::Expired(hash) => hash.to_string(),
+            PoolError::PoolFull(hash) => hash.to_string(),
         }
     }
 }
 
+/// Largest total encoded size of all pending and queued transactions.
+pub const MAX_POOL_SIZE: usize = 64 * 1024 * 1024;
+
+/// The latest block the pool knows of, transactions are checked against the block after it.
+#[derive(Clone, Copy, Debug, Default)]
+struct Head {
+    number: u64,
+    timestamp: u64,
+}
+
+impl Head {
+    fn validity(&self, tx: &PoolTransaction) -> Validity {
+        // the next block's timestamp isn't known yet, the head's one is a lower bound for it
+        tx.transaction
+            .transaction
+            .validity(self.number + 1, self.timestamp)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct TxPool {
-    txs: BTreeMap<TransactionId, PoolTransaction>,
+    /// Transactions that can be included in the next block.
+    pending: BTreeMap<TransactionId, PoolTransaction>,
+    /// Transactions whose validity window hasn't opened yet.
+    queued: BTreeMap<TransactionId, PoolTransaction>,
+    head: Head,
+    /// Total encoded size of all pending and queued transactions.
+    size: usize,
 }
 
 impl TxPool {
     pub fn new() -> Self {
         TxPool {
-            txs: BTreeMap::new(),
+            pending: BTreeMap::new(),
+            queued: BTreeMap::new(),
+            head: Head::default(),
+            size: 0,
         }
     }
 
-    fn remove_transaction(&mut self, tx: &PoolTransaction) -> Option<PoolTransaction> {
-        let internal = self.txs.remove(&tx.transaction_id)?;
+    fn remove_transaction(&mut self, id: &TransactionId) -> Option<PoolTransaction> {
+        let internal = self.pending.remove(id).or_else(|| self.queued.remove(id))?;
+        self.size -= internal.size;
 
         Some(internal)
     }
 
+    /// Total encoded size of all transactions in the pool.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
     pub fn on_new_block(&mut self, event: OnNewBlockEvent) -> OnNewBlockOutcome {
+        self.head = Head {
+            number: event.number,
+            timestamp: event.timestamp,
+        };
+
         for tx in &event.mined_transactions {
-            self.remove_transaction(tx);
+            self.remove_transaction(&tx.transaction_id);
         }
 
+        let head = self.head;
+        let mut expired = Vec::new();
+        let mut promoted = Vec::new();
+        for (id, tx) in std::mem::take(&mut self.queued) {
+            match head.validity(&tx) {
+                Validity::NotYetValid => {
+                    self.queued.insert(id, tx);
+                }
+                Validity::Valid => {
+                    promoted.push(tx.clone());
+                    self.pending.insert(id, tx);
+                }
+                Validity::Expired => {
+                    self.size -= tx.size;
+                    expired.push(tx);
+                }
+            }
+        }
+        let mut size = self.size;
+        self.pending.retain(|_, tx| match head.validity(tx) {
+            Validity::Expired => {
+                size -= tx.size;
+                expired.push(tx.clone());
+                false
+            }
+            _ => true,
+        });
+        self.size = size;
+
         OnNewBlockOutcome {
             block_hash: event.hash,
             mined: event.mined_transactions,
+            promoted,
+            expired,
         }
     }
 
+    /// All transactions that can be included in the next block.
     pub fn get_all(&self) -> Vec<Arc<PoolTransaction>> {
-        self.txs.iter().map(|(_, v)| Arc::new(v.clone())).collect()
+        self.pending.values().map(|v| Arc::new(v.clone())).collect()
+    }
+
+    /// All transactions waiting for their validity window to open.
+    pub fn get_queued(&self) -> Vec<Arc<PoolTransaction>> {
+        self.queued.values().map(|v| Arc::new(v.clone())).collect()
+    }
+
+    /// Pending transactions in the order a block should include them: highest fee first, but
+    /// never before a transaction of the same sender with a lower nonce.
+    pub fn best_transactions(&self) -> Vec<Arc<PoolTransaction>> {
+        // pending is sorted by sender then nonce, so each sender's transactions are a run
+        let mut by_sender: Vec<Vec<&PoolTransaction>> = Vec::new();
+        for tx in self.pending.values() {
+            match by_sender.last_mut() {
+                Some(run) if run[0].transaction_id.from == tx.transaction_id.from => run.push(tx),
+                _ => by_sender.push(vec![tx]),
+            }
+        }
+
+        // the next transaction of every sender, the hash breaks ties so every node agrees
+        let mut heads: BinaryHeap<_> = by_sender
+            .iter()
+            .enumerate()
+            .map(|(sender, run)| (run[0].fee, Reverse(&run[0].transaction.hash), sender, 0))
+            .collect();
+        let mut best = Vec::with_capacity(self.pending.len());
+        while let Some((_, _, sender, index)) = heads.pop() {
+            let run = &by_sender[sender];
+            best.push(Arc::new(run[index].clone()));
+            if let Some(next) = run.get(index + 1) {
+                heads.push((next.fee, Reverse(&next.transaction.hash), sender, index + 1));
+            }
+        }
+
+        best
     }
 
     pub fn get(&self, key: TransactionId) -> Option<PoolTransaction> {
-        self.txs.get(&key).map(|tx| tx.clone())
+        self.pending
+            .get(&key)
+            .or_else(|| self.queued.get(&key))
+            .cloned()
     }
 
     pub fn add_transaction(
         &mut self,
         transaction: PoolTransaction,
     ) -> Result<PoolTransaction, PoolError> {
-        match self
-            .txs
-            .insert(transaction.transaction_id.clone(), transaction.clone())
-        {
-            Some(transaction) => Ok(transaction),
-            None => return Err(PoolError::DiscardedOnInsert(transaction.transaction.hash)),
+        let id = transaction.transaction_id.clone();
+        let validity = self.head.validity(&transaction);
+        if validity == Validity::Expired {
+            return Err(PoolError::Expired(transaction.transaction.hash));
+        }
+
+        // a transaction with the same id gets replaced, so its size is freed
+        let replaced = self.get(id.clone()).map_or(0, |tx| tx.size);
+        if self.size - replaced + transaction.size > MAX_POOL_SIZE {
+            return Err(PoolError::PoolFull(transaction.transaction.hash));
         }
+        self.remove_transaction(&id);
+        self.size += transaction.size;
+
+        if validity == Validity::NotYetValid {
+            self.queued.insert(id, transaction.clone());
+        } else {
+            self.pending.insert(id, transaction.clone());
+        }
+
+        Ok(transaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use felipeum_primitives::{
+        signature::{Signature, SignatureScheme, TransactionSignature},
+        transaction::{Timelock, Transaction},
+    };
+
+    use super::*;
+
+    fn pool_transaction(
+        nonce: u64,
+        valid_after: Option<Timelock>,
+        valid_until: Option<Timelock>,
+    ) -> PoolTransaction {
+        PoolTransaction::from(TransactionSigned {
+            hash: format!("tx-{nonce}"),
+            signature: TransactionSignature::Single(Signature::new(
+                SignatureScheme::Ed25519,
+                &[0u8; 64],
+            )),
+            transaction: Transaction {
+                fee: U256::one(),
+                valid_after,
+                valid_until,
+                ..Transaction::transfer("alice", "bob", 1, nonce)
+            },
+        })
+    }
+
+    fn new_block(number: u64) -> OnNewBlockEvent {
+        OnNewBlockEvent {
+            hash: format!("block-{number}"),
+            number,
+            timestamp: 0,
+            mined_transactions: vec![],
+        }
+    }
+
+    #[test]
+    fn timelocked_transactions() {
+        let mut pool = TxPool::new();
+
+        pool.add_transaction(pool_transaction(0, Some(Timelock::Height(3)), None))
+            .unwrap();
+        pool.add_transaction(pool_transaction(1, None, Some(Timelock::Height(2))))
+            .unwrap();
+        assert_eq!(pool.get_all().len(), 1);
+        assert_eq!(pool.get_queued().len(), 1);
+
+        // block 3 is next, so the first transaction becomes includable and the second expires
+        let outcome = pool.on_new_block(new_block(2));
+        assert_eq!(outcome.promoted.len(), 1);
+        assert_eq!(outcome.expired.len(), 1);
+        assert_eq!(pool.get_all()[0].transaction_id.nonce, 0);
+        assert!(pool.get_queued().is_empty());
+
+        assert!(matches!(
+            pool.add_transaction(pool_transaction(2, None, Some(Timelock::Height(1)))),
+            Err(PoolError::Expired(_))
+        ));
+    }
+
+    #[test]
+    fn size_accounting() {
+        let mut pool = TxPool::new();
+        let tx = pool_transaction(0, None, None);
+        let size = tx.size;
+
+        pool.add_transaction(tx.clone()).unwrap();
+        // resubmitting replaces the entry instead of counting it twice
+        pool.add_transaction(tx.clone()).unwrap();
+        assert_eq!(pool.size(), size);
+
+        let mut block = new_block(1);
+        block.mined_transactions.push(tx);
+        pool.on_new_block(block);
+        assert_eq!(pool.size(), 0);
+    }
+
+    #[test]
+    fn best_transactions_order() {
+        let mut pool = TxPool::new();
+        let with = |from: &str, nonce, fee: u64| {
+            let mut tx = pool_transaction(nonce, None, None).transaction;
+            tx.hash = format!("{from}-{nonce}");
+            tx.transaction.from = from.to_string();
+            tx.transaction.fee = U256::from(fee);
+            PoolTransaction::from(tx)
+        };
+        // alice pays little first and a lot after, bob pays in between
+        pool.add_transaction(with("alice", 0, 1)).unwrap();
+        pool.add_transaction(with("alice", 1, 10)).unwrap();
+        pool.add_transaction(with("bob", 0, 5)).unwrap();
+        pool.add_transaction(with("carol", 0, 5)).unwrap();
+
+        let best: Vec<_> = pool
+            .best_transactions()
+            .iter()
+            .map(|tx| tx.transaction.hash.clone())
+            .collect();
+        assert_eq!(best, ["bob-0", "carol-0", "alice-0", "alice-1"]);
     }
 }